@@ -1,11 +1,12 @@
 
 extern crate web_sys;
+extern crate js_sys;
 extern crate virtual_filesystem;
 
 mod utils;
 
 use wasm_bindgen::prelude::*;
-use virtual_filesystem::virtual_filesystem::shell::{CommandError, Shell};
+use virtual_filesystem::virtual_filesystem::shell::Shell;
 use virtual_filesystem::virtual_filesystem_core::logger::LoggerRepository;
 
 macro_rules! log {
@@ -25,6 +26,19 @@ impl LoggerRepository for ConsoleLoggerRepository {
     }
 }
 
+#[wasm_bindgen]
+pub struct Completion {
+    pub start: usize,
+    candidates: Vec<String>,
+}
+
+#[wasm_bindgen]
+impl Completion {
+    pub fn candidates(&self) -> Box<[JsValue]> {
+        self.candidates.iter().map(|x| JsValue::from_str(x)).collect::<Vec<JsValue>>().into_boxed_slice()
+    }
+}
+
 #[wasm_bindgen]
 pub struct Cli {
     shell: Shell<ConsoleLoggerRepository>,
@@ -34,20 +48,33 @@ pub struct Cli {
 impl Cli {
     pub fn new() -> Cli {
         utils::set_panic_hook();
-        Cli {
-            shell: Shell::init_with_logger(ConsoleLoggerRepository{})
+        let mut shell = Shell::init_with_logger(ConsoleLoggerRepository{});
+        shell.set_clock(|| (js_sys::Date::now() / 1000.0) as u64);
+        Cli { shell }
+    }
+
+    pub fn set_readonly(&mut self, readonly: bool) {
+        self.shell.set_readonly(readonly);
+    }
+
+    // offsets are counted in utf-16 code units like javascript strings
+    pub fn complete(&self, line: &str, cursor: usize) -> Completion {
+        let mut offset = 0;
+        let mut units = 0;
+        for c in line.chars() {
+            if units >= cursor { break }
+            units += c.len_utf16();
+            offset += c.len_utf8();
         }
+        let (start, candidates) = self.shell.complete(line, offset);
+        Completion { start: line[..start].encode_utf16().count(), candidates }
     }
 
     pub fn run(&mut self, command: &str) -> String {
         match self.shell.run(command) {
             Ok(None) => { "".to_string() },
             Ok(Some(response)) => { format!("{}", response) },
-            Err(CommandError::UnknownError) => { format!("unknown error.") },
-            Err(CommandError::NotFound) => { format!("not found.") },
-            Err(CommandError::IllegalArgument) => { format!("illegal argument.") },
-            Err(CommandError::NotFile) => { format!("not file.") },
-            Err(CommandError::CommandNotFound(command)) => { format!("{} command not found.", command) },
+            Err(e) => { e.to_string() },
         }
     }
 }
@@ -11,22 +11,22 @@ fn test_ls() {
     assert_eq!(shell.run(buffer), "".to_string());
 
     let buffer = "ls a";
-    assert_eq!(shell.run(buffer), "".to_string());
+    assert_eq!(shell.run(buffer), format!("not found."));
 
-    let buffer = "ls b c";
+    let buffer = "mkdir a";
     assert_eq!(shell.run(buffer), "".to_string());
 
-    let buffer = "mkdir a";
+    let buffer = "touch .b; touch c";
     assert_eq!(shell.run(buffer), "".to_string());
 
     let buffer = "ls";
-    assert_eq!(shell.run(buffer), "a".to_string());
+    assert_eq!(shell.run(buffer), "a\tc".to_string());
 
-    let buffer = "ls a";
-    assert_eq!(shell.run(buffer), "a".to_string());
+    let buffer = "ls -a1";
+    assert_eq!(shell.run(buffer), ".b\na\nc".to_string());
 
-    let buffer = "ls b c";
-    assert_eq!(shell.run(buffer), "a".to_string());
+    let buffer = "ls a c";
+    assert_eq!(shell.run(buffer), "c\n\na:".to_string());
 }
 
 #[wasm_bindgen_test]
@@ -157,7 +157,7 @@ fn test_mkdir() {
     assert_eq!(shell.run(buffer), "".to_string());
 
     let buffer = "ls";
-    assert_eq!(shell.run(buffer), "a\tb\ta".to_string());
+    assert_eq!(shell.run(buffer), "a\ta\tb".to_string());
 }
 
 #[wasm_bindgen_test]
@@ -177,7 +177,7 @@ fn test_touch() {
     assert_eq!(shell.run(buffer), "".to_string());
 
     let buffer = "ls";
-    assert_eq!(shell.run(buffer), "a\tb\ta".to_string());
+    assert_eq!(shell.run(buffer), "a\ta\tb".to_string());
 }
 
 #[wasm_bindgen_test]
@@ -256,3 +256,63 @@ fn test_write() {
     assert_eq!(shell.run(buffer), format!("not file."));
 }
 
+
+#[wasm_bindgen_test]
+fn test_readonly() {
+    let shell = &mut Cli::new();
+
+    let buffer = "touch a";
+    assert_eq!(shell.run(buffer), "".to_string());
+
+    shell.set_readonly(true);
+
+    let buffer = "mkdir dir";
+    assert_eq!(shell.run(buffer), format!("read-only filesystem."));
+
+    let buffer = "write a 123";
+    assert_eq!(shell.run(buffer), format!("read-only filesystem."));
+
+    let buffer = "read a";
+    assert_eq!(shell.run(buffer), "".to_string());
+}
+
+#[wasm_bindgen_test]
+fn test_complete() {
+    let shell = &mut Cli::new();
+
+    let buffer = "mkdir dir";
+    assert_eq!(shell.run(buffer), "".to_string());
+
+    let completion = shell.complete("ls | gr", 7);
+    assert_eq!(completion.start, 5);
+    assert_eq!(completion.candidates().len(), 1);
+
+    let completion = shell.complete("echo é d", 8);
+    assert_eq!(completion.start, 7);
+    assert_eq!(completion.candidates()[0].as_string(), Some("dir/".to_string()));
+}
+
+#[wasm_bindgen_test]
+fn test_help() {
+    let shell = &mut Cli::new();
+
+    let buffer = "help head";
+    assert_eq!(shell.run(buffer), "usage: head [-n lines] [file]\nprint the first lines\n  -n lines  the number of lines, 10 by default".to_string());
+
+    let buffer = "ls --help";
+    assert_eq!(shell.run(buffer), "usage: ls [-a] [-l] [-R] [-1] [-S] [-t] [-r] [path...]\nlist directories, the current directory without an argument\n  -a  list the names starting with a dot\n  -l  list the permissions, sizes and modification times\n  -R  list the subdirectories recursively\n  -1  list one name per line\n  -S  sort by size, largest first\n  -t  sort by modification time, newest first\n  -r  reverse the order".to_string());
+}
+
+#[wasm_bindgen_test]
+fn test_tree() {
+    let shell = &mut Cli::new();
+
+    let buffer = "mkdir a; touch b; cd a; touch c; cd /";
+    assert_eq!(shell.run(buffer), "".to_string());
+
+    let buffer = "tree";
+    assert_eq!(shell.run(buffer), ".\n├── a\n│   └── c\n└── b\n\n1 directory, 2 files".to_string());
+
+    let buffer = "tree -L 1";
+    assert_eq!(shell.run(buffer), ".\n├── a\n└── b\n\n1 directory, 1 file".to_string());
+}
@@ -11,3 +11,28 @@ runButton.addEventListener("click", event => {
     commandOutput.innerHTML = cli.run(command);
 });
 
+commandText.addEventListener("keydown", event => {
+    if (event.key !== "Tab") { return; }
+    event.preventDefault();
+
+    const line = commandText.value;
+    const cursor = commandText.selectionStart;
+    const completion = cli.complete(line, cursor);
+    const start = completion.start;
+    const candidates = completion.candidates();
+    completion.free();
+    if (candidates.length === 0) { return; }
+
+    let replacement = candidates.reduce((common, x) => {
+        let i = 0;
+        while (i < common.length && common[i] === x[i]) { i++; }
+        return common.slice(0, i);
+    });
+    if (candidates.length === 1 && !replacement.endsWith("/")) { replacement += " "; }
+    if (candidates.length > 1 && replacement.length <= cursor - start) {
+        commandOutput.innerHTML = candidates.join("  ");
+        return;
+    }
+    commandText.value = line.slice(0, start) + replacement + line.slice(cursor);
+    commandText.selectionStart = commandText.selectionEnd = start + replacement.length;
+});
@@ -1,5 +1,7 @@
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
 use virtual_filesystem::virtual_filesystem::command::pwd;
-use virtual_filesystem::virtual_filesystem::shell::{CommandError, Buffer, Shell};
+use virtual_filesystem::virtual_filesystem::shell::{CommandError, CommandResult, Buffer, Shell};
 use virtual_filesystem::virtual_filesystem_core::logger::LoggerRepository;
 
 
@@ -11,43 +13,309 @@ impl LoggerRepository for MockLoggerRepository {
 }
 
 
+// puts the terminal into raw mode with stty and restores it when dropped
+struct RawMode {
+    saved: String,
+}
+
+
+impl RawMode {
+    fn enable() -> Option<RawMode> {
+        let output = Command::new("stty").arg("-g").stdin(Stdio::inherit()).output().ok()?;
+        if !output.status.success() { return None }
+        let saved = String::from_utf8(output.stdout).ok()?.trim().to_string();
+        let status = Command::new("stty").args(["raw", "-echo"]).stdin(Stdio::inherit()).status().ok()?;
+        if !status.success() { return None }
+        Some(RawMode { saved })
+    }
+}
+
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = Command::new("stty").arg(&self.saved).stdin(Stdio::inherit()).status();
+    }
+}
+
+
+enum Key {
+    Char(char),
+    Control(u8),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    Unknown,
+}
+
+
+fn read_key<R: Read>(bytes: &mut std::io::Bytes<R>) -> Option<Key> {
+    let mut next = || bytes.next().and_then(|x| x.ok());
+    let key = match next()? {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        127 | 8 => Key::Backspace,
+        0x1b => match (next()?, next()?) {
+            (b'[', b'A') => Key::Up,
+            (b'[', b'B') => Key::Down,
+            (b'[', b'C') => Key::Right,
+            (b'[', b'D') => Key::Left,
+            (b'[', b'H') | (b'O', b'H') => Key::Home,
+            (b'[', b'F') | (b'O', b'F') => Key::End,
+            (b'[', c) if c.is_ascii_digit() => match (c, next()?) {
+                (b'1', b'~') | (b'7', b'~') => Key::Home,
+                (b'4', b'~') | (b'8', b'~') => Key::End,
+                (b'3', b'~') => Key::Delete,
+                _ => Key::Unknown,
+            },
+            _ => Key::Unknown,
+        },
+        c if c < 0x20 => Key::Control(c + b'@'),
+        c if c < 0x80 => Key::Char(c as char),
+        c => {
+            // the length of an utf-8 sequence is given by its first byte
+            let length = if c >= 0xf0 { 4 } else if c >= 0xe0 { 3 } else { 2 };
+            let mut buffer = vec![c];
+            for _ in 1..length { buffer.push(next()?); }
+            String::from_utf8(buffer).ok().and_then(|x| x.chars().next()).map(Key::Char).unwrap_or(Key::Unknown)
+        },
+    };
+    Some(key)
+}
+
+
+struct Editor {
+    prompt: String,
+    line: Vec<char>,
+    cursor: usize,
+    history: Vec<String>,
+    position: usize,
+    draft: Vec<char>,
+}
+
+
+impl Editor {
+    fn new(prompt: &str, history: Vec<String>) -> Editor {
+        let position = history.len();
+        Editor { prompt: prompt.to_string(), line: vec![], cursor: 0, history, position, draft: vec![] }
+    }
+
+    fn text(&self) -> String {
+        self.line.iter().collect()
+    }
+
+    fn redraw(&self) {
+        let mut output = format!("\r{}{}\x1b[K", self.prompt, self.text());
+        if self.cursor < self.line.len() {
+            output.push_str(&format!("\x1b[{}D", self.line.len() - self.cursor));
+        }
+        print!("{}", output);
+        let _ = std::io::stdout().flush();
+    }
+
+    fn insert(&mut self, text: &str) {
+        for c in text.chars() {
+            self.line.insert(self.cursor, c);
+            self.cursor += 1;
+        }
+    }
+
+    fn delete_word(&mut self) {
+        let mut start = self.cursor;
+        while start > 0 && self.line[start - 1].is_whitespace() { start -= 1; }
+        while start > 0 && !self.line[start - 1].is_whitespace() { start -= 1; }
+        self.line.drain(start..self.cursor);
+        self.cursor = start;
+    }
+
+    fn recall(&mut self, position: usize) {
+        if self.position == self.history.len() { self.draft = self.line.clone(); }
+        self.position = position;
+        self.line = match self.history.get(position) {
+            Some(line) => line.chars().collect(),
+            None => self.draft.clone(),
+        };
+        self.cursor = self.line.len();
+    }
+
+    fn complete<T: LoggerRepository>(&mut self, shell: &Shell<T>) {
+        let text = self.text();
+        let offset = self.line[..self.cursor].iter().map(|x| x.len_utf8()).sum();
+        let (start, candidates) = shell.complete(&text, offset);
+        let start = text[..start].chars().count();
+        let word: String = self.line[start..self.cursor].iter().collect();
+
+        let common = candidates.iter().skip(1).fold(candidates.first().cloned().unwrap_or_default(), |common, x| {
+            common.chars().zip(x.chars()).take_while(|(a, b)| a == b).map(|(a, _)| a).collect()
+        });
+        let replacement = match candidates.as_slice() {
+            [] => {
+                print!("\x07");
+                return
+            },
+            [candidate] if !candidate.ends_with('/') => format!("{} ", candidate),
+            [candidate] => candidate.to_string(),
+            _ if common.chars().count() > word.chars().count() => common,
+            _ => {
+                // nothing more can be inserted, so the candidates are listed
+                print!("\r\n{}\r\n", candidates.join("  "));
+                return
+            },
+        };
+        self.line.drain(start..self.cursor);
+        self.cursor = start;
+        self.insert(&replacement);
+    }
+}
+
+
+// reads a line with cursor movement, history recall and tab completion,
+// falling back to plain input when the terminal can not be put in raw mode
+fn read_line<T: LoggerRepository>(shell: &Shell<T>, prompt: &str) -> Option<String> {
+    print!("{}", prompt);
+    let _ = std::io::stdout().flush();
+    let raw = match RawMode::enable() {
+        Some(raw) => raw,
+        None => {
+            let mut buffer = Buffer::new();
+            return match std::io::stdin().read_line(&mut buffer) {
+                Ok(0) | Err(_) => None,
+                Ok(_) => Some(buffer),
+            };
+        },
+    };
+
+    let history = shell.command_history().into_iter().map(|x| x.1.to_string()).collect();
+    let editor = &mut Editor::new(prompt, history);
+    let stdin = std::io::stdin();
+    let bytes = &mut stdin.lock().bytes();
+    let result = loop {
+        let key = match read_key(bytes) {
+            Some(key) => key,
+            None => break None,
+        };
+        match key {
+            Key::Enter => break Some(editor.text()),
+            Key::Char(c) => editor.insert(&c.to_string()),
+            Key::Tab => editor.complete(shell),
+            Key::Backspace if editor.cursor > 0 => {
+                editor.cursor -= 1;
+                editor.line.remove(editor.cursor);
+            },
+            Key::Delete if editor.cursor < editor.line.len() => { editor.line.remove(editor.cursor); },
+            Key::Left if editor.cursor > 0 => editor.cursor -= 1,
+            Key::Right if editor.cursor < editor.line.len() => editor.cursor += 1,
+            Key::Up if editor.position > 0 => editor.recall(editor.position - 1),
+            Key::Down if editor.position < editor.history.len() => editor.recall(editor.position + 1),
+            Key::Home | Key::Control(b'A') => editor.cursor = 0,
+            Key::End | Key::Control(b'E') => editor.cursor = editor.line.len(),
+            Key::Control(b'W') => editor.delete_word(),
+            Key::Control(b'U') => {
+                editor.line.drain(..editor.cursor);
+                editor.cursor = 0;
+            },
+            // the line is discarded and a new one is read
+            Key::Control(b'C') => {
+                print!("^C");
+                break Some(String::new())
+            },
+            Key::Control(b'D') if editor.line.is_empty() => break None,
+            _ => {},
+        }
+        editor.redraw();
+    };
+    print!("\r\n");
+    let _ = std::io::stdout().flush();
+    drop(raw);
+    result
+}
+
+
+fn print(result: CommandResult) {
+    match result {
+        Ok(None) => {},
+        Ok(Some(response)) => { println!("{}", response) },
+        Err(CommandError::Failure(_)) => {},
+        Err(e) => { println!("{}", e) },
+    }
+}
+
+
+// errors go to stderr when the shell is not interactive
+fn report(result: CommandResult) {
+    match result {
+        Ok(None) => {},
+        Ok(Some(response)) => { println!("{}", response) },
+        Err(CommandError::Partial(output, e)) => {
+            println!("{}", output);
+            if !matches!(*e, CommandError::Failure(_)) { eprintln!("{}", e) }
+        },
+        Err(CommandError::Failure(_)) => {},
+        Err(e) => { eprintln!("{}", e) },
+    }
+}
+
+
+// the status of the last command, or of the last failure which was not
+// tested by `&&`, `||` or a condition
+fn run_batch(script: &str) -> i32 {
+    let mut shell = Shell::init();
+    shell.set_history_expansion(false);
+    report(shell.rc.clone());
+    let status = shell.run_each(script, report);
+    if status != 0 { status } else { shell.failure }
+}
+
+
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.iter().map(|x| x.as_str()).collect::<Vec<&str>>().as_slice() {
+        [] => {},
+        ["--script", path] => {
+            let status = match std::fs::read_to_string(path) {
+                Ok(script) => run_batch(&script),
+                Err(e) => {
+                    eprintln!("{}: {}", path, e);
+                    1
+                },
+            };
+            std::process::exit(status);
+        },
+        ["-c", commands] => { std::process::exit(run_batch(commands)); },
+        _ => {
+            eprintln!("usage: cli [--script <file> | -c <commands>]");
+            std::process::exit(2);
+        },
+    }
+
     println!("start interactive shell. Enjoy! :/");
-    println!("to stop, press Ctrl + c or type exit");
+    println!("to stop, press Ctrl + d or type exit");
     println!("if you need help, type :?");
 
     let mut shell = Shell::init();
+    print(shell.rc.clone());
     
     loop {
-        println!("[{}] $> ", pwd(&shell.current));
-        let mut buffer = Buffer::new();
-        std::io::stdin().read_line(&mut buffer).unwrap();
+        let prompt = format!("[{}] $> ", pwd(&shell.current));
+        let buffer = match read_line(&shell, &prompt) {
+            Some(buffer) => buffer,
+            None => break,
+        };
         let buffer = buffer.trim();
 
         if buffer == "exit" { break }
         else if buffer == ":?" {
-            println!("to stop, press Ctrl + c or type exit");
-            println!("command list");
-            println!("  ls");
-            println!("  pwd");
-            println!("  cd [directory]");
-            println!("  find [path]");
-            println!("  mkdir [directory]");
-            println!("  touch [file]");
-            println!("  read [file]");
-            println!("  write [file] [string]");
-            println!("  exit");
+            println!("to stop, press Ctrl + d or type exit");
+            if let Ok(Some(help)) = shell.run("help") { println!("{}", help) }
             continue
         }
 
-        match shell.run(buffer) {
-            Ok(None) => {},
-            Ok(Some(response)) => { println!("{}", response) },
-            Err(CommandError::UnknownError) => { println!("unknown error.") },
-            Err(CommandError::NotFound) => { println!("not found.") },
-            Err(CommandError::IllegalArgument) => { println!("illegal argument.") },
-            Err(CommandError::NotFile) => { println!("not file.") },
-            Err(CommandError::CommandNotFound(command)) => { println!("{} command not found.", command) },
-        }
+        print(shell.run(buffer));
     }
 }
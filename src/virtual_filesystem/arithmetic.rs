@@ -0,0 +1,206 @@
+use crate::virtual_filesystem::shell::CommandError;
+
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Operator(String),
+}
+
+
+const OPERATORS: [&str; 17] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<", ">", "+", "-", "*", "/", "%", "!", "(", ")", "$",
+];
+// parentheses and unary operators nested deeper fail instead of overflowing
+// the stack
+const MAX_NESTING: usize = 64;
+
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, CommandError> {
+    let mut tokens = vec![];
+    let chars: Vec<char> = expression.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_alphanumeric() { i += 1; }
+            let number: String = chars[start..i].iter().collect();
+            tokens.push(Token::Number(number.parse().map_err(|_| CommandError::IllegalArgument)?));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') { i += 1; }
+            tokens.push(Token::Name(chars[start..i].iter().collect()));
+        } else {
+            let rest: String = chars[i..].iter().take(2).collect();
+            let operator = OPERATORS.iter()
+                .find(|x| rest.starts_with(*x))
+                .ok_or(CommandError::IllegalArgument)?;
+            tokens.push(Token::Operator(operator.to_string()));
+            i += operator.chars().count();
+        }
+    }
+    Ok(tokens)
+}
+
+
+struct Evaluator<'a, F: Fn(&str) -> Option<String>> {
+    tokens: &'a [Token],
+    position: usize,
+    variable: F,
+    depth: usize,
+    // the right operand of `&&` and `||` is only parsed when the left one
+    // decides the result
+    skipping: bool,
+}
+
+
+impl<'a, F: Fn(&str) -> Option<String>> Evaluator<'a, F> {
+    fn accept(&mut self, operators: &[&str]) -> Option<String> {
+        match self.tokens.get(self.position) {
+            Some(Token::Operator(operator)) if operators.contains(&operator.as_str()) => {
+                self.position += 1;
+                Some(operator.to_string())
+            },
+            _ => None,
+        }
+    }
+
+    // parses the operators of one precedence level, each level binding
+    // tighter than the previous one
+    fn binary(&mut self, level: usize) -> Result<i64, CommandError> {
+        const LEVELS: [&[&str]; 6] = [&["||"], &["&&"], &["==", "!="], &["<", "<=", ">", ">="], &["+", "-"], &["*", "/", "%"]];
+        if level == LEVELS.len() { return self.unary(); }
+
+        let mut left = self.binary(level + 1)?;
+        while let Some(operator) = self.accept(LEVELS[level]) {
+            let decided = match operator.as_str() {
+                "||" => left != 0,
+                "&&" => left == 0,
+                _ => false,
+            };
+            let skipping = self.skipping;
+            self.skipping = skipping || decided;
+            let right = self.binary(level + 1);
+            self.skipping = skipping;
+            let right = right?;
+            left = match operator.as_str() {
+                "||" => ((left != 0) || (right != 0)) as i64,
+                "&&" => ((left != 0) && (right != 0)) as i64,
+                "==" => (left == right) as i64,
+                "!=" => (left != right) as i64,
+                "<" => (left < right) as i64,
+                "<=" => (left <= right) as i64,
+                ">" => (left > right) as i64,
+                ">=" => (left >= right) as i64,
+                "+" => self.checked(left.checked_add(right))?,
+                "-" => self.checked(left.checked_sub(right))?,
+                "*" => self.checked(left.checked_mul(right))?,
+                "/" => self.checked(left.checked_div(right))?,
+                _ => self.checked(left.checked_rem(right))?,
+            };
+        }
+        Ok(left)
+    }
+
+    // a skipped operand can not fail
+    fn checked(&self, value: Option<i64>) -> Result<i64, CommandError> {
+        if self.skipping { return Ok(value.unwrap_or_default()); }
+        value.ok_or(CommandError::IllegalArgument)
+    }
+
+    fn unary(&mut self) -> Result<i64, CommandError> {
+        if self.depth >= MAX_NESTING { return Err(CommandError::IllegalArgument); }
+        self.depth += 1;
+        let value = match self.accept(&["-", "+", "!"]).as_deref() {
+            Some("-") => self.unary().and_then(|x| self.checked(x.checked_neg())),
+            Some("+") => self.unary(),
+            Some(_) => self.unary().map(|x| (x == 0) as i64),
+            None => self.primary(),
+        };
+        self.depth -= 1;
+        value
+    }
+
+    fn primary(&mut self) -> Result<i64, CommandError> {
+        if self.accept(&["("]).is_some() {
+            let value = self.binary(0)?;
+            self.accept(&[")"]).ok_or(CommandError::IllegalArgument)?;
+            return Ok(value);
+        }
+        let dollar = self.accept(&["$"]).is_some();
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        match token {
+            Some(Token::Number(number)) if !dollar => Ok(number),
+            Some(Token::Name(_)) if self.skipping => Ok(0),
+            Some(Token::Name(name)) => {
+                // unset and empty variables count as zero
+                let value = (self.variable)(&name).unwrap_or_default();
+                if value.trim().is_empty() { Ok(0) }
+                else { value.trim().parse::<i64>().map_err(|_| CommandError::IllegalArgument) }
+            },
+            _ => Err(CommandError::IllegalArgument),
+        }
+    }
+}
+
+
+pub fn evaluate<F: Fn(&str) -> Option<String>>(expression: &str, variable: F) -> Result<i64, CommandError> {
+    let tokens = tokenize(expression)?;
+    let mut evaluator = Evaluator { tokens: &tokens, position: 0, variable, depth: 0, skipping: false };
+    let value = evaluator.binary(0)?;
+    if evaluator.position != tokens.len() { return Err(CommandError::IllegalArgument); }
+    Ok(value)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::virtual_filesystem::shell::CommandError;
+    use crate::virtual_filesystem::arithmetic::evaluate;
+
+    #[test]
+    fn test_evaluate() {
+        let variable = |name: &str| match name {
+            "x" => Some("6".to_string()),
+            "empty" => Some("".to_string()),
+            "text" => Some("abc".to_string()),
+            _ => None,
+        };
+        let evaluate = |expression: &str| evaluate(expression, variable);
+
+        assert_eq!(evaluate("1 + 2 * 3"), Ok(7));
+        assert_eq!(evaluate("(1 + 2) * 3"), Ok(9));
+        assert_eq!(evaluate("10 - 4 - 3"), Ok(3));
+        assert_eq!(evaluate("7 / 2 + 7 % 2"), Ok(4));
+        assert_eq!(evaluate("-x + +2"), Ok(-4));
+        assert_eq!(evaluate("$x * x"), Ok(36));
+        assert_eq!(evaluate("missing + empty"), Ok(0));
+        assert_eq!(evaluate("1 < 2 && 2 <= 2 && 3 > 2 && 3 >= 4 || 5 == 5"), Ok(1));
+        assert_eq!(evaluate("!0 + !7 + (1 != 1)"), Ok(1));
+        assert_eq!(evaluate("1 / 0"), Err(CommandError::IllegalArgument));
+        assert_eq!(evaluate("9223372036854775807 + 1"), Err(CommandError::IllegalArgument));
+        assert_eq!(evaluate("text + 1"), Err(CommandError::IllegalArgument));
+        assert_eq!(evaluate("1 +"), Err(CommandError::IllegalArgument));
+        assert_eq!(evaluate("(1"), Err(CommandError::IllegalArgument));
+        assert_eq!(evaluate("1 2"), Err(CommandError::IllegalArgument));
+        assert_eq!(evaluate("1a"), Err(CommandError::IllegalArgument));
+        assert_eq!(evaluate(""), Err(CommandError::IllegalArgument));
+
+        // the right operand of `&&` and `||` is not evaluated when the left one decides
+        assert_eq!(evaluate("0 && 1 / 0"), Ok(0));
+        assert_eq!(evaluate("1 || text + 9223372036854775807 * 2"), Ok(1));
+        assert_eq!(evaluate("1 && 1 / 0"), Err(CommandError::IllegalArgument));
+        assert_eq!(evaluate("0 || 1 / 0"), Err(CommandError::IllegalArgument));
+
+        // deep nesting fails instead of overflowing the stack
+        let nested = |depth: usize| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(evaluate(&nested(16)), Ok(1));
+        assert_eq!(evaluate(&nested(2000)), Err(CommandError::IllegalArgument));
+        assert_eq!(evaluate(&format!("{}1", "-".repeat(2001))), Err(CommandError::IllegalArgument));
+    }
+}
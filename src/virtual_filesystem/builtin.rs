@@ -0,0 +1,719 @@
+use crate::virtual_filesystem_core::filesystem::{FileNodePointer, FileObject};
+use crate::virtual_filesystem_core::logger::LoggerRepository;
+use crate::virtual_filesystem::command::{children, pwd as print_directory, read, find as find_child, resolve};
+use crate::virtual_filesystem::listing::{Entry, Order, sort as sort_entries, long, columns, tree as draw_tree, summary};
+use crate::virtual_filesystem::history::{Version, diff_lines};
+use crate::virtual_filesystem::parser::is_name;
+use crate::virtual_filesystem::registry::{Arguments, Builtin, CommandOption, Definition, Registry, ShellCommand};
+use crate::virtual_filesystem::shell::{Arg, CommandError, CommandResult, Control, ExitStatus, Shell};
+
+
+const LS_OPTIONS: [CommandOption; 7] = [
+    CommandOption::flag("-a", "list the names starting with a dot"),
+    CommandOption::flag("-l", "list the permissions, sizes and modification times"),
+    CommandOption::flag("-R", "list the subdirectories recursively"),
+    CommandOption::flag("-1", "list one name per line"),
+    CommandOption::flag("-S", "sort by size, largest first"),
+    CommandOption::flag("-t", "sort by modification time, newest first"),
+    CommandOption::flag("-r", "reverse the order"),
+];
+
+const DEFAULT_COLUMNS: usize = 80;
+
+const TREE_OPTIONS: [CommandOption; 1] = [
+    CommandOption::value("-L", "depth", "descend at most depth levels"),
+];
+
+const RM_OPTIONS: [CommandOption; 1] = [
+    CommandOption::flag("-r", "remove directories and their contents"),
+];
+
+const SNAPSHOT_OPTIONS: [CommandOption; 2] = [
+    CommandOption::flag("-l", "list the snapshots"),
+    CommandOption::value("-d", "from", "show the changes from a snapshot"),
+];
+
+const COMMIT_OPTIONS: [CommandOption; 1] = [
+    CommandOption::value("-m", "message", "commit the tree with a message"),
+];
+
+const LOG_OPTIONS: [CommandOption; 1] = [
+    CommandOption::flag("-c", "list the commits"),
+];
+
+const CHECKOUT_OPTIONS: [CommandOption; 1] = [
+    CommandOption::value("-c", "id", "check out a commit"),
+];
+
+const GREP_OPTIONS: [CommandOption; 1] = [
+    CommandOption::flag("-v", "print the lines not containing the pattern"),
+];
+
+const SORT_OPTIONS: [CommandOption; 1] = [
+    CommandOption::flag("-r", "sort in reverse order"),
+];
+
+const HEAD_OPTIONS: [CommandOption; 1] = [
+    CommandOption::value("-n", "lines", "the number of lines, 10 by default"),
+];
+
+const UNALIAS_OPTIONS: [CommandOption; 1] = [
+    CommandOption::flag("-a", "remove every alias"),
+];
+
+const HISTORY_OPTIONS: [CommandOption; 1] = [
+    CommandOption::flag("-c", "clear the history"),
+];
+const TEST_OPTIONS: [CommandOption; 16] = [
+    CommandOption::flag("-a", "both expressions are true"),
+    CommandOption::flag("-d", "the path is a directory"),
+    CommandOption::flag("-e", "the path exists"),
+    CommandOption::flag("-eq", "the integers are equal"),
+    CommandOption::flag("-f", "the path is a file"),
+    CommandOption::flag("-ge", "the first integer is greater than or equal to the second"),
+    CommandOption::flag("-gt", "the first integer is greater than the second"),
+    CommandOption::flag("-le", "the first integer is less than or equal to the second"),
+    CommandOption::flag("-lt", "the first integer is less than the second"),
+    CommandOption::flag("-n", "the string is not empty"),
+    CommandOption::flag("-ne", "the integers are not equal"),
+    CommandOption::flag("-o", "either expression is true"),
+    CommandOption::flag("-r", "the path exists, every path is readable"),
+    CommandOption::flag("-s", "the path is a file or a directory which is not empty"),
+    CommandOption::flag("-w", "the path is writable"),
+    CommandOption::flag("-z", "the string is empty"),
+];
+
+
+const SYNTAX: &str = "\
+redirection
+  [command] > [file]
+  [command] >> [file]
+  [command] < [file]
+  [command] | [command]
+sequence
+  [command] ; [command]
+  [command] && [command]
+  [command] || [command]
+  $? is the exit status of the last command
+variable
+  $[name] | ${[name]}
+  [name]=[value]
+  $1 .. $9 | $# | $@
+  $([command]) | $(([expression]))
+history
+  !! | ![n] | !-[n] | ![prefix] | !?[text]?
+control
+  if [list]; then [list]; elif [list]; then [list]; else [list]; fi
+  for [name] in [words]; do [list]; done
+  while [list]; do [list]; done | until [list]; do [list]; done
+  [name]() { [list]; }
+glob
+  * | ? | [...] | **";
+
+
+pub fn registry<T: LoggerRepository>() -> Registry<T> {
+    let builtins: Vec<Builtin<T>> = vec![
+        Builtin::new("ls", "[path...]", "list directories, the current directory without an argument", ls).with_options(&LS_OPTIONS),
+        Builtin::new("tree", "[path]", "print the hierarchy below a directory", tree).with_options(&TREE_OPTIONS),
+        Builtin::new("pwd", "", "print the current directory", pwd),
+        Builtin::new("cd", "[directory | -]", "change the current directory, to $HOME without an argument", cd),
+        Builtin::new("find", "[path]", "find a file in the current directory", find),
+        Builtin::new("mkdir", "[directory]", "make a directory", mkdir),
+        Builtin::new("touch", "[file]", "make an empty file", touch),
+        Builtin::new("read", "[file]", "print the contents of a file", read_file),
+        Builtin::new("write", "[file] [string]", "append a string or the input to a file", write),
+        Builtin::new("rm", "[path...]", "remove files and directories", rm).with_options(&RM_OPTIONS),
+        Builtin::new("snapshot", "[name] | [to]", "take a snapshot, list the snapshots or show the changes between them", snapshot)
+            .with_options(&SNAPSHOT_OPTIONS),
+        Builtin::new("restore", "[name]", "restore a snapshot", restore),
+        Builtin::new("undo", "", "undo the last change", undo),
+        Builtin::new("redo", "", "redo the last undone change", redo),
+        Builtin::new("begin", "", "begin a transaction", begin),
+        Builtin::new("commit", "", "commit the transaction or the tree", commit)
+            .with_options(&COMMIT_OPTIONS),
+        Builtin::new("rollback", "", "roll back the transaction", rollback),
+        Builtin::new("status", "", "show the changes since the last commit", status),
+        Builtin::new("log", "[file]", "list the versions of a file or the commits", log)
+            .with_options(&LOG_OPTIONS),
+        Builtin::new("diff", "[file] [from] [to]", "show the changes between versions of a file", diff),
+        Builtin::new("checkout", "[file] [version]", "check out a version of a file, the last commit without a version", checkout)
+            .with_options(&CHECKOUT_OPTIONS),
+        Builtin::new("wc", "[file]", "count the lines, words and bytes", wc),
+        Builtin::new("grep", "[pattern] [file]", "print the lines containing a pattern", grep)
+            .with_options(&GREP_OPTIONS),
+        Builtin::new("sort", "[file]", "sort the lines", sort)
+            .with_options(&SORT_OPTIONS),
+        Builtin::new("head", "[file]", "print the first lines", head)
+            .with_options(&HEAD_OPTIONS),
+        Builtin::new("echo", "[string...]", "print the arguments", echo),
+        Builtin::new("source", "[file] [args...]", "run the commands in a file", source),
+        Builtin::new("sh", "[file] [args...]", "run the commands in a file", source),
+        Builtin::new("test", "[expression]", "evaluate an expression", test).with_options(&TEST_OPTIONS).with_raw_arguments(),
+        Builtin::new("[", "[expression] ]", "evaluate an expression", bracket).with_options(&TEST_OPTIONS).with_raw_arguments(),
+        Builtin::new("true", "", "succeed", |_, _, _| Ok(None)),
+        Builtin::new("false", "", "fail", |_, _, _| Err(CommandError::Failure(1))),
+        Builtin::new("break", "", "leave the loop", |shell, _, _| end_iteration(shell, Control::Break)),
+        Builtin::new("continue", "", "continue with the next iteration", |shell, _, _| end_iteration(shell, Control::Continue)),
+        Builtin::new("return", "[status]", "return from a function or a script", return_status),
+        Builtin::new("shift", "[n]", "shift the positional parameters", shift),
+        Builtin::new("export", "[name]=[value]...", "set environment variables", export),
+        Builtin::new("unset", "[name...]", "remove environment variables", unset),
+        Builtin::new("alias", "[name]=[value]... | [name...]", "define or show aliases", alias),
+        Builtin::new("unalias", "[name...]", "remove aliases", unalias)
+            .with_options(&UNALIAS_OPTIONS),
+        Builtin::new("history", "[n]", "list the last n lines entered", history)
+            .with_options(&HISTORY_OPTIONS),
+        Builtin::new("help", "[command]", "show the usage of a command or list every command", help),
+        Builtin::new("env", "", "list the environment variables", env),
+        Builtin::new("set", "", "list the environment variables", env),
+    ];
+
+    let mut registry = Registry::new();
+    for builtin in builtins {
+        registry.register(Definition::Builtin(builtin));
+    }
+    registry
+}
+
+
+fn ls<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, _: Option<String>) -> CommandResult {
+    let paths = if args.operands.is_empty() { vec!["."] } else { args.operands.clone() };
+    let mut files = vec![];
+    let mut directories = vec![];
+    for path in &paths {
+        let node = resolve(&shell.root, &shell.current, path)?;
+        if node.borrow().0.is_directory() {
+            directories.push((path.to_string(), node));
+        } else {
+            files.push(entry(shell, path.to_string(), &node));
+        }
+    }
+    directories.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut sections = vec![];
+    if !files.is_empty() { sections.push(format_entries(shell, args, files)); }
+    // every directory is named when there is more than one listing
+    let header = paths.len() > 1 || args.has("-R");
+    for (path, directory) in directories {
+        list_directory(shell, args, &path, &directory, header, &mut sections);
+    }
+    Ok(Some(sections.join("\n\n")))
+}
+
+
+fn list_directory<T: LoggerRepository>(shell: &Shell<T>, args: &Arguments, path: &str, directory: &FileNodePointer, header: bool, sections: &mut Vec<String>) {
+    let entries: Vec<Entry> = children(directory).iter()
+        .map(|x| entry(shell, x.borrow().0.name().to_string(), x))
+        .filter(|x| args.has("-a") || !x.name.starts_with('.'))
+        .collect();
+    let subdirectories: Vec<(String, FileNodePointer)> = if args.has("-R") {
+        let mut entries = entries.clone();
+        sort_entries(&mut entries, order(args), args.has("-r"));
+        entries.into_iter()
+            .filter(|x| x.directory && x.name != "." && x.name != "..")
+            .map(|x| (format!("{}/{}", path.trim_end_matches('/'), x.name), x.node))
+            .collect()
+    } else { vec![] };
+
+    let listing = format_entries(shell, args, entries);
+    sections.push(if header { format!("{}:\n{}", path, listing).trim_end().to_string() } else { listing });
+    for (path, node) in subdirectories {
+        list_directory(shell, args, &path, &node, header, sections);
+    }
+}
+
+
+fn entry<T: LoggerRepository>(shell: &Shell<T>, name: String, node: &FileNodePointer) -> Entry {
+    let directory = node.borrow().0.is_directory();
+    let size = if directory { children(node).len() } else { read(node).map(|x| x.len()).unwrap_or_default() };
+    Entry {
+        name,
+        node: node.clone(),
+        directory,
+        writable: shell.check_writable(node).is_ok(),
+        size,
+        modified: shell.modified(node),
+    }
+}
+
+
+fn order(args: &Arguments) -> Order {
+    if args.has("-S") { Order::Size } else if args.has("-t") { Order::Time } else { Order::Name }
+}
+
+
+fn format_entries<T: LoggerRepository>(shell: &Shell<T>, args: &Arguments, mut entries: Vec<Entry>) -> String {
+    sort_entries(&mut entries, order(args), args.has("-r"));
+    if args.has("-l") { return long(&entries); }
+
+    let names: Vec<String> = entries.into_iter().map(|x| x.name).collect();
+    // one name per line when the output is not shown directly
+    if args.has("-1") || args.redirected { return names.join("\n"); }
+    let width = shell.variable("COLUMNS").and_then(|x| x.parse().ok()).unwrap_or(DEFAULT_COLUMNS);
+    columns(&names, width)
+}
+
+
+fn tree<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, _: Option<String>) -> CommandResult {
+    // the depth may also follow the path as in `tree docs -L 1`
+    let (path, depth) = match args.operands.as_slice() {
+        [] => (".", args.value("-L")),
+        [path] => (*path, args.value("-L")),
+        [path, rest @ ..] => {
+            let trailing = Arguments::parse(&TREE_OPTIONS, rest)?;
+            if !trailing.operands.is_empty() { return Err(CommandError::IllegalArgument); }
+            (*path, trailing.value("-L").or_else(|| args.value("-L")))
+        },
+    };
+    let depth = match depth {
+        Some(depth) => match depth.parse::<usize>() {
+            Ok(depth) if depth > 0 => Some(depth),
+            _ => return Err(CommandError::IllegalArgument),
+        },
+        None => None,
+    };
+
+    let node = resolve(&shell.root, &shell.current, path)?;
+    let (lines, directories, files) = if node.borrow().0.is_directory() { draw_tree(&node, depth) } else { (vec![], 0, 1) };
+    let mut result = vec![path.to_string()];
+    result.extend(lines);
+    result.push(String::new());
+    result.push(summary(directories, files));
+    Ok(Some(result.join("\n")))
+}
+
+
+fn pwd<T: LoggerRepository>(shell: &mut Shell<T>, _: &Arguments, _: Option<String>) -> CommandResult {
+    Ok(Some(print_directory(&shell.current)))
+}
+
+
+fn cd<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, _: Option<String>) -> CommandResult {
+    let (path, print) = match args.operand(0) {
+        Some("-") => (shell.variable("OLDPWD").ok_or(CommandError::IllegalArgument)?, true),
+        Some(arg) => (arg.to_string(), false),
+        None => (shell.variable("HOME").ok_or(CommandError::IllegalArgument)?, false),
+    };
+    let change = resolve(&shell.root, &shell.current, &path)?;
+    let previous = print_directory(&shell.current);
+    shell.current = change;
+    shell.set_variable("OLDPWD", &previous);
+    if print { Ok(Some(print_directory(&shell.current))) } else { Ok(None) }
+}
+
+
+fn export<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, input: Option<String>) -> CommandResult {
+    if args.operands.is_empty() { return env(shell, args, input); }
+    for arg in &args.operands {
+        let (name, value) = match arg.find('=') {
+            Some(i) => (&arg[..i], Some(&arg[i + 1..])),
+            None => (*arg, None),
+        };
+        if !is_name(name) { return Err(CommandError::IllegalArgument); }
+        let value = value.map(|x| x.to_string())
+            .unwrap_or_else(|| shell.variable(name).unwrap_or_default());
+        shell.set_variable(name, &value);
+    }
+    Ok(None)
+}
+
+
+fn unset<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, _: Option<String>) -> CommandResult {
+    if args.operands.is_empty() { return Err(CommandError::IllegalArgument); }
+    for arg in &args.operands {
+        if !is_name(arg) { return Err(CommandError::IllegalArgument); }
+        shell.unset_variable(arg);
+    }
+    Ok(None)
+}
+
+
+fn alias<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, _: Option<String>) -> CommandResult {
+    if args.operands.is_empty() {
+        let result = shell.aliases.iter()
+            .map(|(name, value)| format!("alias {}='{}'", name, value))
+            .collect::<Vec<String>>();
+        return Ok(Some(result.join("\n")));
+    }
+    let mut result = vec![];
+    for arg in &args.operands {
+        match arg.find('=') {
+            Some(i) => {
+                if arg[..i].is_empty() || arg[..i].contains(|c: char| c.is_whitespace() || c == '/') {
+                    return Err(CommandError::IllegalArgument);
+                }
+                shell.set_alias(&arg[..i], &arg[i + 1..]);
+            },
+            None => {
+                let value = shell.aliases.get(*arg).ok_or(CommandError::NotFound)?;
+                result.push(format!("alias {}='{}'", arg, value));
+            },
+        }
+    }
+    if result.is_empty() { Ok(None) } else { Ok(Some(result.join("\n"))) }
+}
+
+
+fn unalias<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, _: Option<String>) -> CommandResult {
+    if args.has("-a") {
+        shell.aliases.clear();
+        return Ok(None);
+    }
+    if args.operands.is_empty() { return Err(CommandError::IllegalArgument); }
+    for arg in &args.operands {
+        shell.unset_alias(arg)?;
+    }
+    Ok(None)
+}
+
+
+fn history<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, _: Option<String>) -> CommandResult {
+    if args.has("-c") {
+        shell.commands.clear();
+        shell.save_history()?;
+        return Ok(None);
+    }
+    let entries = shell.commands.entries();
+    let count = match args.operand(0) {
+        Some(n) => n.parse::<usize>().map_err(|_| CommandError::IllegalArgument)?,
+        None => entries.len(),
+    };
+    let result = entries[entries.len() - count.min(entries.len())..].iter()
+        .map(|(number, line)| format!("{:>5}  {}", number, line))
+        .collect::<Vec<String>>();
+    Ok(Some(result.join("\n")))
+}
+
+
+fn help<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, _: Option<String>) -> CommandResult {
+    if let Some(name) = args.operand(0) {
+        let definition = shell.command(name).ok_or_else(|| CommandError::CommandNotFound(name.to_string()))?;
+        return Ok(Some(definition.help()));
+    }
+    let mut result = vec!["command list".to_string()];
+    for name in shell.command_names() {
+        if let Some(definition) = shell.command(name) {
+            result.push(format!("  {}", definition.usage()));
+        }
+    }
+    result.push(SYNTAX.to_string());
+    Ok(Some(result.join("\n")))
+}
+
+
+fn env<T: LoggerRepository>(shell: &mut Shell<T>, _: &Arguments, _: Option<String>) -> CommandResult {
+    let result = shell.variables().iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<String>>();
+    Ok(Some(result.join("\n")))
+}
+
+
+fn find<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, _: Option<String>) -> CommandResult {
+    let arg = args.operand(0).ok_or(CommandError::IllegalArgument)?;
+    let pointer = find_child(&shell.current, arg)?;
+    let name = pointer.borrow().0.name().to_string();
+    Ok(Some(name))
+}
+
+
+fn mkdir<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, _: Option<String>) -> CommandResult {
+    shell.check_writable(&shell.current)?;
+    let arg = args.operand(0).ok_or(CommandError::IllegalArgument)?;
+    let current = shell.current.clone();
+    shell.make_directory(&current, arg)?;
+    Ok(None)
+}
+
+
+fn touch<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, _: Option<String>) -> CommandResult {
+    shell.check_writable(&shell.current)?;
+    let arg = args.operand(0).ok_or(CommandError::IllegalArgument)?;
+    let current = shell.current.clone();
+    shell.touch_file(&current, arg)?;
+    Ok(None)
+}
+
+
+fn read_file<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, _: Option<String>) -> CommandResult {
+    let arg = args.operand(0).ok_or(CommandError::IllegalArgument)?;
+    Ok(Some(shell.read_path(arg)?))
+}
+
+
+fn write<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, input: Option<String>) -> CommandResult {
+    let arg = args.operand(0).ok_or(CommandError::IllegalArgument)?;
+    let data = &args.operands[1..];
+    let data = if !data.is_empty() { data.join(" ") }
+        else if let Some(input) = input { input }
+        else { return Err(CommandError::IllegalArgument); };
+    let current = shell.current.clone();
+    shell.write_file(&current, arg, &data, true).map(|_| None)
+}
+
+
+fn echo<T: LoggerRepository>(_: &mut Shell<T>, args: &Arguments, _: Option<String>) -> CommandResult {
+    Ok(Some(args.operands.join(" ")))
+}
+
+
+fn source<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, _: Option<String>) -> CommandResult {
+    let script = shell.read_path(args.operand(0).ok_or(CommandError::IllegalArgument)?)?;
+    let args: Vec<String> = args.operands[1..].iter().map(|x| x.to_string()).collect();
+    if args.is_empty() { return shell.run_script(&script); }
+    let positional = std::mem::replace(&mut shell.positional, args);
+    let result = shell.run_script(&script);
+    shell.positional = positional;
+    result
+}
+
+
+fn test_file<T: LoggerRepository>(shell: &Shell<T>, operator: &Arg, path: &Arg) -> bool {
+    let node = match resolve(&shell.root, &shell.current, path) {
+        Ok(node) => node,
+        Err(_) => return false,
+    };
+    let directory = node.borrow().0.is_directory();
+    match operator {
+        "-e" => true,
+        "-f" => !directory,
+        "-d" => directory,
+        // the size of a directory is its number of entries
+        "-s" if directory => !children(&node).is_empty(),
+        "-s" => !read(&node).unwrap_or_default().is_empty(),
+        // there are no read permissions, a path which exists is readable
+        "-r" => true,
+        "-w" => shell.check_writable(&node).is_ok(),
+        _ => false,
+    }
+}
+
+
+// evaluates the arguments of `test` with the precedence `!`, `-a`, `-o`
+fn test_expression<T: LoggerRepository>(shell: &Shell<T>, args: &[&Arg]) -> Result<bool, CommandError> {
+    fn integer(arg: &Arg) -> Result<i64, CommandError> {
+        arg.trim().parse::<i64>().map_err(|_| CommandError::IllegalArgument)
+    }
+
+    if let Some(i) = args.iter().rposition(|x| x == &"-o") {
+        return Ok(test_expression(shell, &args[..i])? | test_expression(shell, &args[i + 1..])?);
+    }
+    if let Some(i) = args.iter().rposition(|x| x == &"-a") {
+        return Ok(test_expression(shell, &args[..i])? & test_expression(shell, &args[i + 1..])?);
+    }
+    match args {
+        ["!", rest @ ..] if !rest.is_empty() => Ok(!test_expression(shell, rest)?),
+        [] => Ok(false),
+        [arg] => Ok(!arg.is_empty()),
+        ["-n", arg] => Ok(!arg.is_empty()),
+        ["-z", arg] => Ok(arg.is_empty()),
+        [operator @ ("-e" | "-f" | "-d" | "-s" | "-r" | "-w"), path] => Ok(test_file(shell, operator, path)),
+        [a, "=", b] | [a, "==", b] => Ok(a == b),
+        [a, "!=", b] => Ok(a != b),
+        [a, "-eq", b] => Ok(integer(a)? == integer(b)?),
+        [a, "-ne", b] => Ok(integer(a)? != integer(b)?),
+        [a, "-lt", b] => Ok(integer(a)? < integer(b)?),
+        [a, "-le", b] => Ok(integer(a)? <= integer(b)?),
+        [a, "-gt", b] => Ok(integer(a)? > integer(b)?),
+        [a, "-ge", b] => Ok(integer(a)? >= integer(b)?),
+        _ => Err(CommandError::IllegalArgument),
+    }
+}
+
+
+fn test<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, _: Option<String>) -> CommandResult {
+    if test_expression(shell, &args.operands)? { Ok(None) } else { Err(CommandError::Failure(1)) }
+}
+
+
+fn bracket<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, input: Option<String>) -> CommandResult {
+    match args.operands.split_last() {
+        Some((&"]", rest)) => test(shell, &Arguments::new(rest.to_vec()), input),
+        _ => Err(CommandError::IllegalArgument),
+    }
+}
+
+
+fn end_iteration<T: LoggerRepository>(shell: &mut Shell<T>, control: Control) -> CommandResult {
+    if shell.loops == 0 { return Err(CommandError::IllegalArgument); }
+    shell.control = Some(control);
+    Ok(None)
+}
+
+
+fn return_status<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, _: Option<String>) -> CommandResult {
+    if shell.depth == 0 { return Err(CommandError::IllegalArgument); }
+    let status = match args.operand(0) {
+        Some(arg) => arg.parse::<ExitStatus>().map_err(|_| CommandError::IllegalArgument)?,
+        None => shell.exit_status,
+    };
+    shell.control = Some(Control::Return);
+    if status == 0 { Ok(None) } else { Err(CommandError::Failure(status)) }
+}
+
+
+fn shift<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, _: Option<String>) -> CommandResult {
+    let count = match args.operand(0) {
+        Some(arg) => arg.parse::<usize>().map_err(|_| CommandError::IllegalArgument)?,
+        None => 1,
+    };
+    if count > shell.positional.len() { return Err(CommandError::IllegalArgument); }
+    shell.positional.drain(..count);
+    Ok(None)
+}
+
+
+fn wc<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, input: Option<String>) -> CommandResult {
+    let data = shell.input(args.operands.first(), input)?;
+    let lines = data.lines().count();
+    let words = data.split_whitespace().count();
+    Ok(Some(format!("{} {} {}", lines, words, data.len())))
+}
+
+
+fn grep<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, input: Option<String>) -> CommandResult {
+    let invert = args.has("-v");
+    let pattern = args.operand(0).ok_or(CommandError::IllegalArgument)?;
+    let data = shell.input(args.operands.get(1), input)?;
+    let result = data.lines()
+        .filter(|x| x.contains(pattern) != invert)
+        .collect::<Vec<&str>>();
+    Ok(Some(result.join("\n")))
+}
+
+
+fn sort<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, input: Option<String>) -> CommandResult {
+    let data = shell.input(args.operands.first(), input)?;
+    let mut result = data.lines().collect::<Vec<&str>>();
+    result.sort_unstable();
+    if args.has("-r") { result.reverse(); }
+    Ok(Some(result.join("\n")))
+}
+
+
+fn head<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, input: Option<String>) -> CommandResult {
+    let count = match args.value("-n") {
+        Some(n) => n.parse::<usize>().map_err(|_| CommandError::IllegalArgument)?,
+        None => 10,
+    };
+    let data = shell.input(args.operands.first(), input)?;
+    let result = data.lines().take(count).collect::<Vec<&str>>();
+    Ok(Some(result.join("\n")))
+}
+
+
+fn rm<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, _: Option<String>) -> CommandResult {
+    if args.operands.is_empty() { return Err(CommandError::IllegalArgument); }
+    shell.remove_paths(&args.operands, args.has("-r")).map(|_| None)
+}
+
+
+fn snapshot<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, _: Option<String>) -> CommandResult {
+    if let Some(from) = args.value("-d") {
+        return Ok(Some(shell.diff_snapshots(from, args.operand(0))?.join("\n")));
+    }
+    match args.operand(0) {
+        Some(name) if !args.has("-l") => {
+            shell.snapshot(name).map(|_| None)
+        },
+        _ => {
+            let names = shell.snapshots.iter()
+                .map(|x| x.name.to_string())
+                .collect::<Vec<String>>();
+            Ok(Some(names.join("\t")))
+        },
+    }
+}
+
+
+fn restore<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, _: Option<String>) -> CommandResult {
+    let arg = args.operand(0).ok_or(CommandError::IllegalArgument)?;
+    shell.restore(arg).map(|_| None)
+}
+
+
+fn undo<T: LoggerRepository>(shell: &mut Shell<T>, _: &Arguments, _: Option<String>) -> CommandResult {
+    shell.undo().map(|_| None)
+}
+
+
+fn redo<T: LoggerRepository>(shell: &mut Shell<T>, _: &Arguments, _: Option<String>) -> CommandResult {
+    shell.redo().map(|_| None)
+}
+
+
+fn begin<T: LoggerRepository>(shell: &mut Shell<T>, _: &Arguments, _: Option<String>) -> CommandResult {
+    shell.begin();
+    Ok(None)
+}
+
+
+fn commit<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, _: Option<String>) -> CommandResult {
+    match args.value("-m") {
+        Some(message) => {
+            let message = std::iter::once(message).chain(args.operands.iter().copied()).collect::<Vec<&Arg>>().join(" ");
+            if message.is_empty() { return Err(CommandError::IllegalArgument); }
+            let id = shell.commit_tree(&message);
+            Ok(Some(id[..7].to_string()))
+        },
+        None => shell.commit().map(|_| None),
+    }
+}
+
+
+fn rollback<T: LoggerRepository>(shell: &mut Shell<T>, _: &Arguments, _: Option<String>) -> CommandResult {
+    shell.rollback().map(|_| None)
+}
+
+
+fn status<T: LoggerRepository>(shell: &mut Shell<T>, _: &Arguments, _: Option<String>) -> CommandResult {
+    Ok(Some(shell.status().join("\n")))
+}
+
+
+fn log<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, _: Option<String>) -> CommandResult {
+    if args.has("-c") {
+        if !args.operands.is_empty() { return Err(CommandError::IllegalArgument); }
+        let result = shell.commits().iter()
+            .map(|(id, message)| format!("{}\t{}", &id[..7], message))
+            .collect::<Vec<String>>();
+        Ok(Some(result.join("\n")))
+    } else if let Some(arg) = args.operand(0) {
+        let versions = shell.versions(arg)?;
+        let result = versions.iter().rev()
+            .map(|(version, data)| {
+                let mut lines = data.lines();
+                let head = lines.next().unwrap_or("");
+                let more = if lines.next().is_some() { " ..." } else { "" };
+                format!("{}\t{}{}", version, head, more)
+            })
+            .collect::<Vec<String>>();
+        Ok(Some(result.join("\n")))
+    } else {
+        Err(CommandError::IllegalArgument)
+    }
+}
+
+
+fn diff<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, _: Option<String>) -> CommandResult {
+    if let (Some(arg), Some(from)) = (args.operand(0), args.operands.get(1)) {
+        let before = shell.version_argument(arg, Some(from))?;
+        let after = shell.version_argument(arg, args.operands.get(2))?;
+        Ok(Some(diff_lines(&before, &after).join("\n")))
+    } else {
+        Err(CommandError::IllegalArgument)
+    }
+}
+
+
+fn checkout<T: LoggerRepository>(shell: &mut Shell<T>, args: &Arguments, _: Option<String>) -> CommandResult {
+    match (args.value("-c"), args.operand(0), args.operand(1)) {
+        (Some(id), None, _) => { shell.checkout_commit(id).map(|_| None) },
+        (None, Some(arg), Some(version)) => {
+            let version = version.parse::<Version>().map_err(|_| CommandError::IllegalArgument)?;
+            shell.checkout_version(arg, version).map(|_| None)
+        },
+        (None, Some(arg), None) => { shell.checkout_file(arg).map(|_| None) },
+        _ => { Err(CommandError::IllegalArgument) },
+    }
+}
@@ -1,12 +1,57 @@
-use crate::virtual_filesystem_core::graph::{NodePointer, Graph};
+use crate::virtual_filesystem_core::graph::{NodePointer, Edge, Graph};
 use crate::virtual_filesystem_core::filesystem::{FileNode, FileNodePointer, FileType, FileObject, Name, Data};
+use crate::virtual_filesystem::shell::CommandError;
+
+
+fn is_whiteout(node: &FileNodePointer) -> bool {
+    matches!(node.borrow().0, FileType::Whiteout{ .. })
+}
+
+
+fn is_child(directory: &FileNodePointer, node: &FileNodePointer) -> bool {
+    match node.borrow().1.first() {
+        Some(parent) => NodePointer::ptr_eq(parent, directory),
+        None => false,
+    }
+}
+
+
+pub fn children(directory: &FileNodePointer) -> Vec<FileNodePointer> {
+    let node = directory.borrow();
+    let upper: Vec<FileNodePointer> = node.1.iter().skip(1).cloned().collect();
+
+    let lower = if let FileType::Overlay{ name: _, lower } = &node.0 {
+        lower
+    } else {
+        return upper.into_iter().filter(|x| !is_whiteout(x)).collect();
+    };
+
+    // lower entries keep their position, upper entries shadow them by name
+    // and whiteouts hide them.
+    let mut shadowed = vec![false; upper.len()];
+    let mut result = vec![];
+    for l in children(lower) {
+        let name = l.borrow().0.name().to_string();
+        if let Some(i) = upper.iter().position(|x| x.borrow().0.name() == &name) {
+            if shadowed[i] { continue }
+            shadowed[i] = true;
+            if !is_whiteout(&upper[i]) { result.push(upper[i].clone()); }
+        } else {
+            result.push(l);
+        }
+    }
+    for (i, u) in upper.iter().enumerate() {
+        if !shadowed[i] && !is_whiteout(u) { result.push(u.clone()); }
+    }
+    result
+}
 
 
 pub fn ls(directory: &FileNodePointer) -> String {
-    let nodes = &directory.borrow().1;
-    let mut iter = nodes.iter();
-    let _ = iter.next();
-    iter.map(|x| x.borrow().0.name().to_string()).collect::<Vec<String>>().join("\t")
+    children(directory).iter()
+        .map(|x| x.borrow().0.name().to_string())
+        .collect::<Vec<String>>()
+        .join("\t")
 }
 
 
@@ -43,33 +88,53 @@ pub fn touch(directory: &FileNodePointer, name: Name, data: Data) {
 }
 
 
-pub fn write(file: &FileNodePointer, input: &str) -> Result<(), ()> {
+// the tree is seen through an overlay so that the caller's nodes are neither
+// renamed nor re-parented, and writes are copied up into the mount point.
+pub fn mount(directory: &FileNodePointer, name: Name, node: FileNodePointer) {
+    directory.borrow_mut().connect(
+        FileNode::create_overlay(name, node, vec![directory.clone()]).to_pointer()
+    );
+}
+
+
+pub fn write(file: &FileNodePointer, input: &str) -> Result<(), CommandError> {
+    let n = &mut file.borrow_mut().0;
+
+    match n {
+        FileType::File{ name: _, data } => {
+            *data = data.to_string() + input;
+            Ok(())
+        },
+        _ => { Err(CommandError::NotFile) }
+    }
+}
+
+
+pub fn overwrite(file: &FileNodePointer, input: &str) -> Result<(), CommandError> {
     let n = &mut file.borrow_mut().0;
 
     match n {
         FileType::File{ name: _, data } => {
-            *data = data.to_string() + &input;
+            *data = input.to_string();
             Ok(())
         },
-        _ => { Err(()) }
+        _ => { Err(CommandError::NotFile) }
     }
 }
 
 
-pub fn read(file: &FileNodePointer) -> Result<Data, ()> {
+pub fn read(file: &FileNodePointer) -> Result<Data, CommandError> {
     let n = &file.borrow().0;
 
     match n {
         FileType::File{ name: _, data } => { Ok(data.to_string()) },
-        _ => { Err(()) }
+        _ => { Err(CommandError::NotFile) }
     }
 }
 
 
-pub fn find(directory: &FileNodePointer, target: &str) -> Result<NodePointer<FileType>, ()> {
-    let edges = &directory.borrow().1;
-
-    for e in edges {
+pub fn find(directory: &FileNodePointer, target: &str) -> Result<NodePointer<FileType>, CommandError> {
+    for e in children(directory) {
         let s = e.borrow()
             .0
             .name()
@@ -77,11 +142,211 @@ pub fn find(directory: &FileNodePointer, target: &str) -> Result<NodePointer<Fil
 
 
         if s == target {
-            return Ok(NodePointer::clone(e));
+            // directories of the lower layer are seen through an overlay which
+            // is only connected to the upper layer by `materialize`, so that
+            // a lookup does not change the tree.
+            if e.borrow().0.is_directory() && !is_child(directory, &e) {
+                return Ok(FileNode::create_overlay(s, e.clone(), vec![directory.clone()]).to_pointer());
+            }
+            return Ok(e);
+        }
+    }
+
+    Err(CommandError::NotFound)
+}
+
+
+fn is_connected(directory: &FileNodePointer, node: &FileNodePointer) -> bool {
+    directory.borrow().1.iter().skip(1).any(|x| NodePointer::ptr_eq(x, node))
+}
+
+
+// the overlays returned by `find` are connected with their ancestors before a
+// directory is modified. an overlay connected meanwhile under the same name is
+// used instead. the nodes whose edges change are returned with their edges
+// before the change.
+pub fn materialize(directory: &FileNodePointer) -> (FileNodePointer, Vec<(FileNodePointer, Edge<FileType>)>) {
+    fn attach(node: &FileNodePointer, changed: &mut Vec<(FileNodePointer, Edge<FileType>)>) -> FileNodePointer {
+        let parent = match node.borrow().1.first() {
+            Some(parent) if !NodePointer::ptr_eq(parent, node) => parent.clone(),
+            _ => return node.clone(),
+        };
+        let upper = attach(&parent, changed);
+        if NodePointer::ptr_eq(&upper, &parent) && is_connected(&parent, node) { return node.clone(); }
+
+        let name = node.borrow().0.name().to_string();
+        let existing = upper.borrow().1.iter().skip(1)
+            .find(|x| is_child(&upper, x) && !is_whiteout(x) && x.borrow().0.name() == &name)
+            .cloned();
+        if let Some(existing) = existing { return existing; }
+
+        for x in [node, &upper] {
+            if !changed.iter().any(|(y, _)| NodePointer::ptr_eq(x, y)) {
+                changed.push((x.clone(), x.borrow().1.clone()));
+            }
+        }
+        node.borrow_mut().1[0] = upper.clone();
+        upper.borrow_mut().connect(node.clone());
+        node.clone()
+    }
+
+    let mut changed = vec![];
+    let result = attach(directory, &mut changed);
+    (result, changed)
+}
+
+
+// the node seen at the place of `node`, which changes when another lookup of
+// the same lower directory has been materialized
+pub fn reattach(node: &FileNodePointer) -> FileNodePointer {
+    let parent = match node.borrow().1.first() {
+        Some(parent) if !NodePointer::ptr_eq(parent, node) => parent.clone(),
+        _ => return node.clone(),
+    };
+    let upper = reattach(&parent);
+    if NodePointer::ptr_eq(&upper, &parent) && is_connected(&parent, node) { return node.clone(); }
+    let name = node.borrow().0.name().to_string();
+    find(&upper, &name).unwrap_or_else(|_| node.clone())
+}
+
+
+pub fn split_path(path: &str) -> (&str, &str) {
+    match path.rfind('/') {
+        Some(0) => ("/", &path[1..]),
+        Some(i) => (&path[..i], &path[i + 1..]),
+        None => (".", path),
+    }
+}
+
+
+pub fn resolve(root: &FileNodePointer, current: &FileNodePointer, path: &str) -> Result<FileNodePointer, CommandError> {
+    let mut position = if path.starts_with('/') { root.clone() } else { current.clone() };
+
+    for name in path.split('/') {
+        if name.is_empty() || name == "." { continue }
+        let next = if name == ".." {
+            match position.borrow().1.first() {
+                Some(parent) => parent.clone(),
+                None => return Err(CommandError::UnknownError),
+            }
+        } else {
+            find(&position, name)?
+        };
+        position = next;
+    }
+
+    Ok(position)
+}
+
+
+pub fn diff(from: &FileNodePointer, to: &FileNodePointer) -> Vec<String> {
+    fn walk(from: &FileNodePointer, to: &FileNodePointer, path: &str, result: &mut Vec<String>) {
+        // subtrees shared between both sides are unchanged
+        if NodePointer::ptr_eq(from, to) { return }
+
+        let before = children(from);
+        let after = children(to);
+        let name = |x: &FileNodePointer| x.borrow().0.name().to_string();
+
+        for b in &before {
+            if !after.iter().any(|a| name(a) == name(b)) {
+                result.push(format!("D {}/{}", path, name(b)));
+            }
         }
+        for a in &after {
+            let child = format!("{}/{}", path, name(a));
+            match before.iter().find(|b| name(b) == name(a)) {
+                None => { result.push(format!("A {}", child)); },
+                Some(b) => {
+                    let directories = (b.borrow().0.is_directory(), a.borrow().0.is_directory());
+                    match directories {
+                        (true, true) => { walk(b, a, &child, result); },
+                        (false, false) => {
+                            if read(b) != read(a) { result.push(format!("M {}", child)); }
+                        },
+                        _ => { result.push(format!("M {}", child)); },
+                    }
+                },
+            }
+        }
+    }
+
+    let mut result = vec![];
+    walk(from, to, "", &mut result);
+    result
+}
+
+
+pub fn copy_up(directory: &FileNodePointer, file: &FileNodePointer) -> FileNodePointer {
+    if is_child(directory, file) { return file.clone(); }
+
+    let node = if let FileType::File{ name, data } = &file.borrow().0 {
+        FileNode::create_file(name.to_string(), data.to_string(), vec![directory.clone()]).to_pointer()
+    } else { return file.clone(); };
+    directory.borrow_mut().connect(node.clone());
+    node
+}
+
+
+// the overlays of a tree are replaced by plain directories holding what they
+// show, so that freezing a tree again and again does not stack up layers.
+// nodes without overlays are shared. an overlay for which `keep` returns
+// true for its bottom layer stays a single overlay over that layer.
+pub fn flatten(node: &FileNodePointer, parent: Option<&FileNodePointer>, keep: &dyn Fn(&FileNodePointer) -> bool) -> FileNodePointer {
+    let (name, mut lower) = if let FileType::Overlay{ name, lower } = &node.borrow().0 {
+        (name.to_string(), lower.clone())
+    } else { return node.clone(); };
+    loop {
+        let next = if let FileType::Overlay{ name: _, lower } = &lower.borrow().0 { lower.clone() } else { break };
+        lower = next;
     }
 
-    Err(())
+    let layered = keep(&lower);
+    let visible = children(node);
+    let result = if layered {
+        FileNode::create_overlay(name, lower.clone(), vec![]).to_pointer()
+    } else {
+        FileNode::create_directory(name, vec![]).to_pointer()
+    };
+    let parent = parent.cloned().unwrap_or_else(|| result.clone());
+    result.borrow_mut().connect(parent);
+
+    for entry in &visible {
+        // the entries of the bottom layer are seen through the overlay
+        if layered && is_child(&lower, entry) { continue }
+        let entry = flatten(entry, Some(&result), keep);
+        result.borrow_mut().connect(entry);
+    }
+    if layered {
+        for entry in children(&lower) {
+            let name = entry.borrow().0.name().to_string();
+            if !visible.iter().any(|x| x.borrow().0.name() == &name) {
+                result.borrow_mut().connect(FileNode::create_whiteout(name, vec![result.clone()]).to_pointer());
+            }
+        }
+    }
+    result
+}
+
+
+pub fn rm(directory: &FileNodePointer, target: &str) -> Result<(), CommandError> {
+    let node = find(directory, target)?;
+    directory.borrow_mut().1.retain(|x| !NodePointer::ptr_eq(x, &node));
+
+    let lower = if let FileType::Overlay{ name: _, lower } = &directory.borrow().0 {
+        Some(lower.clone())
+    } else { None };
+    if let Some(lower) = lower {
+        let visible = children(&lower).iter().any(|x| x.borrow().0.name() == target);
+        let hidden = directory.borrow().1.iter().skip(1)
+            .any(|x| is_whiteout(x) && x.borrow().0.name() == target);
+        if visible && !hidden {
+            directory.borrow_mut().connect(
+                FileNode::create_whiteout(target.to_string(), vec![directory.clone()]).to_pointer()
+            );
+        }
+    }
+    Ok(())
 }
 
 
@@ -0,0 +1,155 @@
+use crate::virtual_filesystem_core::filesystem::{FileNodePointer, FileObject};
+use crate::virtual_filesystem::command::{children, resolve};
+use crate::virtual_filesystem::glob;
+
+
+const SEPARATORS: [char; 7] = [';', '|', '&', '(', ')', '<', '>'];
+const KEYWORDS: [&str; 9] = ["if", "then", "elif", "else", "while", "until", "do", "{", "!"];
+
+
+fn is_separator(c: char) -> bool {
+    c.is_whitespace() || SEPARATORS.contains(&c)
+}
+
+
+// the byte offset where the word ending at `cursor` starts, an escaped
+// separator is part of the word
+pub fn word_start(line: &str, cursor: usize) -> usize {
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in line[..cursor].char_indices() {
+        if escaped { escaped = false; }
+        else if c == '\\' { escaped = true; }
+        else if is_separator(c) { start = i + c.len_utf8(); }
+    }
+    start
+}
+
+
+// a name as a single word which is not expanded by the shell
+fn escape(name: &str) -> String {
+    let mut result = String::new();
+    for c in glob::escape(name).chars() {
+        if is_separator(c) || "'\"$!".contains(c) { result.push('\\'); }
+        result.push(c);
+    }
+    result
+}
+
+
+fn unescape(word: &str) -> String {
+    let mut result = String::new();
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        result.push(if c == '\\' { chars.next().unwrap_or(c) } else { c });
+    }
+    result
+}
+
+
+// whether a word after `before` is the name of a command
+pub fn is_command_position(before: &str) -> bool {
+    let before = before.trim_end();
+    match before.chars().last() {
+        None => true,
+        Some(c) if SEPARATORS.contains(&c) && c != ')' && c != '<' && c != '>' => true,
+        Some(_) => {
+            let start = word_start(before, before.len());
+            KEYWORDS.contains(&&before[start..]) && is_command_position(&before[..start])
+        },
+    }
+}
+
+
+// the name of the command whose arguments follow `before`
+pub fn command_name(before: &str) -> Option<&str> {
+    let start = before.rfind(|c: char| c == '\n' || SEPARATORS[..4].contains(&c)).map(|x| x + 1).unwrap_or(0);
+    before[start..].split_whitespace().find(|x| !KEYWORDS.contains(x))
+}
+
+
+pub fn complete_name<'a, I: IntoIterator<Item = &'a str>>(names: I, prefix: &str) -> Vec<String> {
+    let mut result: Vec<String> = names.into_iter()
+        .filter(|x| x.starts_with(prefix))
+        .map(|x| x.to_string())
+        .collect();
+    result.sort();
+    result.dedup();
+    result
+}
+
+
+// the paths starting with `word`, escaped as words. directories end with a slash
+pub fn complete_path(root: &FileNodePointer, current: &FileNodePointer, word: &str) -> Vec<String> {
+    let word = unescape(word);
+    let word = word.as_str();
+    let (parent, prefix) = match word.rfind('/') {
+        Some(i) => (&word[..i + 1], &word[i + 1..]),
+        None => ("", word),
+    };
+    let directory = match resolve(root, current, parent) {
+        Ok(directory) if directory.borrow().0.is_directory() => directory,
+        _ => return vec![],
+    };
+
+    let mut result = vec![];
+    for node in children(&directory) {
+        let name = node.borrow().0.name().to_string();
+        // hidden names are only completed when asked for
+        if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) { continue }
+        let slash = if node.borrow().0.is_directory() { "/" } else { "" };
+        result.push(format!("{}{}{}", escape(parent), escape(&name), slash));
+    }
+    result.sort();
+    result
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::virtual_filesystem::shell::Shell;
+    use crate::virtual_filesystem::completion::{word_start, is_command_position, command_name, complete_name, complete_path};
+
+    #[test]
+    fn test_word() {
+        assert_eq!(word_start("", 0), 0);
+        assert_eq!(word_start("ls di", 5), 3);
+        assert_eq!(word_start("cd a;rea", 8), 5);
+        assert_eq!(word_start("echo é/x", 9), 5);
+        assert_eq!(word_start("echo abc", 6), 5);
+        assert_eq!(word_start("cd my\\ d", 8), 3);
+
+        assert!(is_command_position(""));
+        assert!(is_command_position("ls | "));
+        assert!(is_command_position("if true; then "));
+        assert!(is_command_position("while "));
+        assert!(!is_command_position("ls "));
+        assert!(!is_command_position("echo then "));
+        assert!(!is_command_position("ls > "));
+
+        assert_eq!(command_name("grep "), Some("grep"));
+        assert_eq!(command_name("ls; if test -e "), Some("test"));
+        assert_eq!(command_name("ls | sort "), Some("sort"));
+        assert_eq!(command_name("ls;  "), None);
+    }
+
+    #[test]
+    fn test_complete() {
+        let shell = &mut Shell::init();
+        assert_eq!(shell.run("mkdir docs; mkdir data; touch draft; touch .hidden; cd docs; touch note"), Ok(None));
+
+        assert_eq!(complete_name(vec!["cd", "cat", "cd", "ls"], "c"), vec!["cat", "cd"]);
+        assert_eq!(complete_path(&shell.root, &shell.current, ""), vec!["note"]);
+        assert_eq!(complete_path(&shell.root, &shell.current, "../d"), vec!["../data/", "../docs/", "../draft"]);
+        assert_eq!(complete_path(&shell.root, &shell.current, "/docs/n"), vec!["/docs/note"]);
+        assert_eq!(complete_path(&shell.root, &shell.current, "/."), vec!["/.hidden"]);
+        assert_eq!(complete_path(&shell.root, &shell.current, "/draft/"), Vec::<String>::new());
+        assert_eq!(complete_path(&shell.root, &shell.current, "none/"), Vec::<String>::new());
+
+        // names are escaped so that they stay one word and are not expanded
+        assert_eq!(shell.run("mkdir 'my dir'; cd 'my dir'; touch '[a]*'; touch \"it's\""), Ok(None));
+        assert_eq!(complete_path(&shell.root, &shell.current, "/docs/m"), vec!["/docs/my\\ dir/"]);
+        assert_eq!(complete_path(&shell.root, &shell.current, "../my\\ dir/"), vec!["../my\\ dir/\\[a\\]\\*", "../my\\ dir/it\\'s"]);
+        assert_eq!(shell.run("read \\[a\\]\\* && read it\\'s"), Ok(Some("\n".to_string())));
+    }
+}
@@ -0,0 +1,258 @@
+use crate::virtual_filesystem_core::graph::NodePointer;
+use crate::virtual_filesystem_core::filesystem::{FileNodePointer, FileObject};
+use crate::virtual_filesystem::command::children;
+
+
+const SPECIAL: [char; 5] = ['*', '?', '[', ']', '\\'];
+
+
+pub fn escape(text: &str) -> String {
+    let mut result = String::new();
+    for c in text.chars() {
+        if SPECIAL.contains(&c) { result.push('\\'); }
+        result.push(c);
+    }
+    result
+}
+
+
+pub fn is_pattern(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => { chars.next(); },
+            '*' | '?' | '[' => return true,
+            _ => {},
+        }
+    }
+    false
+}
+
+
+fn unescape(pattern: &str) -> String {
+    let mut result = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => { if let Some(c) = chars.next() { result.push(c); } },
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+
+// matches a bracket expression starting after `[`, returning whether `c`
+// is in the class and the length of the expression including `]`.
+fn class(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let mut i = 0;
+    let negate = matches!(pattern.first(), Some('!') | Some('^'));
+    if negate { i += 1; }
+
+    let mut found = false;
+    let mut first = true;
+    while i < pattern.len() {
+        if pattern[i] == ']' && !first { return Some((found != negate, i + 1)); }
+        first = false;
+
+        let mut low = pattern[i];
+        if low == '\\' && i + 1 < pattern.len() {
+            i += 1;
+            low = pattern[i];
+        }
+        if i + 2 < pattern.len() && pattern[i + 1] == '-' && pattern[i + 2] != ']' {
+            let high = pattern[i + 2];
+            if low <= c && c <= high { found = true; }
+            i += 3;
+        } else {
+            if low == c { found = true; }
+            i += 1;
+        }
+    }
+    None
+}
+
+
+// on a mismatch the last star takes one more character, so the time stays
+// quadratic however many stars the pattern has
+fn matches_chars(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // the pattern after the last star and the name it was tried at
+    let mut star = None;
+    while n < name.len() {
+        // the length of the pattern matching the next character
+        let step = match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                star = Some((p, n));
+                continue
+            },
+            Some('?') => Some(1),
+            Some('[') => match class(&pattern[p + 1..], name[n]) {
+                Some((found, length)) => Some(length + 1).filter(|_| found),
+                // an unterminated bracket is an ordinary character
+                None => Some(1).filter(|_| name[n] == '['),
+            },
+            Some('\\') if p + 1 < pattern.len() => Some(2).filter(|_| name[n] == pattern[p + 1]),
+            Some(c) => Some(1).filter(|_| name[n] == *c),
+            None => None,
+        };
+        match (step, star) {
+            (Some(step), _) => {
+                p += step;
+                n += 1;
+            },
+            (None, Some((after, from))) => {
+                p = after;
+                n = from + 1;
+                star = Some((after, n));
+            },
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|x| *x == '*')
+}
+
+
+pub fn matches(pattern: &str, name: &str) -> bool {
+    // hidden names are only matched by a pattern starting with a dot
+    if name.starts_with('.') && !pattern.starts_with('.') { return false }
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    matches_chars(&pattern, &name)
+}
+
+
+fn join(path: &str, name: &str) -> String {
+    if path.is_empty() { name.to_string() }
+    else if path.ends_with('/') { format!("{}{}", path, name) }
+    else { format!("{}/{}", path, name) }
+}
+
+
+fn descendants(path: &str, directory: &FileNodePointer, result: &mut Vec<(String, FileNodePointer)>) {
+    result.push((path.to_string(), directory.clone()));
+    for node in children(directory) {
+        let name = node.borrow().0.name().to_string();
+        if node.borrow().0.is_directory() && !name.starts_with('.') {
+            descendants(&join(path, &name), &node, result);
+        }
+    }
+}
+
+
+pub fn glob_paths(root: &FileNodePointer, current: &FileNodePointer, pattern: &str) -> Vec<(String, FileNodePointer)> {
+    let mut positions = if pattern.starts_with('/') {
+        vec![("/".to_string(), root.clone())]
+    } else {
+        vec![("".to_string(), current.clone())]
+    };
+
+    for component in pattern.split('/') {
+        if component.is_empty() { continue }
+        let mut next = vec![];
+        for (path, position) in &positions {
+            if component == "**" {
+                descendants(path, position, &mut next);
+            } else if component == "." || component == ".." {
+                let node = if component == "." { Some(position.clone()) } else { position.borrow().1.first().cloned() };
+                if let Some(node) = node { next.push((join(path, component), node)); }
+            } else if position.borrow().0.is_directory() {
+                let literal = !is_pattern(component);
+                let name = unescape(component);
+                for node in children(position) {
+                    let found = node.borrow().0.name().to_string();
+                    if (literal && found == name) || (!literal && matches(component, &found)) {
+                        next.push((join(path, &found), node.clone()));
+                    }
+                }
+            }
+        }
+        positions = next;
+    }
+
+    // a trailing slash only matches directories
+    if pattern.ends_with('/') {
+        positions = positions.into_iter()
+            .filter(|(_, node)| node.borrow().0.is_directory())
+            .map(|(path, node)| (join(&path, ""), node))
+            .collect();
+    }
+    positions.retain(|(path, _)| !path.is_empty());
+    positions.sort_by(|a, b| a.0.cmp(&b.0));
+    positions.dedup_by(|a, b| a.0 == b.0 && NodePointer::ptr_eq(&a.1, &b.1));
+    positions
+}
+
+
+pub fn glob(root: &FileNodePointer, current: &FileNodePointer, pattern: &str) -> Vec<FileNodePointer> {
+    glob_paths(root, current, pattern).into_iter().map(|(_, node)| node).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::virtual_filesystem_core::filesystem::FileObject;
+    use crate::virtual_filesystem::shell::Shell;
+    use crate::virtual_filesystem::glob::{matches, is_pattern, escape, glob, glob_paths};
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("*", "abc"));
+        assert!(matches("*.txt", "a.txt"));
+        assert!(!matches("*.txt", "a.txt.bak"));
+        assert!(matches("a?c", "abc"));
+        assert!(!matches("a?c", "ac"));
+        assert!(matches("[ab]*", "banana"));
+        assert!(!matches("[!ab]*", "banana"));
+        assert!(matches("[^ab]*", "cherry"));
+        assert!(matches("file[0-9]", "file7"));
+        assert!(!matches("file[0-9]", "filex"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("a[", "a["));
+        assert!(matches("\\*", "*"));
+        assert!(!matches("\\*", "a"));
+        assert!(!matches("*", ".hidden"));
+        assert!(matches(".*", ".hidden"));
+        assert!(matches("a*b*c", "aXbXbc"));
+        assert!(!matches("a*b*c", "aXbXbd"));
+        assert!(matches("**", ""));
+        assert!(matches(&"*a".repeat(30), &"a".repeat(60)));
+        assert!(!matches(&format!("{}b", "*a".repeat(30)), &"a".repeat(60)));
+    }
+
+    #[test]
+    fn test_pattern() {
+        assert!(is_pattern("*.txt"));
+        assert!(is_pattern("a[bc]"));
+        assert!(!is_pattern("a.txt"));
+        assert!(!is_pattern("\\*"));
+        assert_eq!(escape("a*b?[c]\\"), "a\\*b\\?\\[c\\]\\\\");
+        assert!(matches(&escape("a*"), "a*"));
+        assert!(!matches(&escape("a*"), "ab"));
+    }
+
+    #[test]
+    fn test_glob() {
+        let shell = &mut Shell::init();
+        assert_eq!(shell.run("mkdir logs; touch a.txt; touch b.txt; touch c.md"), Ok(None));
+        assert_eq!(shell.run("cd logs; touch 1.log; touch 2.log; mkdir old; cd old; touch 0.log; cd /"), Ok(None));
+
+        let paths = |pattern: &str| -> Vec<String> {
+            glob_paths(&shell.root, &shell.current, pattern).into_iter().map(|x| x.0).collect()
+        };
+        assert_eq!(paths("*.txt"), vec!["a.txt", "b.txt"]);
+        assert_eq!(paths("logs/*"), vec!["logs/1.log", "logs/2.log", "logs/old"]);
+        assert_eq!(paths("/logs/?.log"), vec!["/logs/1.log", "/logs/2.log"]);
+        assert_eq!(paths("**/*.log"), vec!["logs/1.log", "logs/2.log", "logs/old/0.log"]);
+        assert_eq!(paths("**"), vec!["logs", "logs/old"]);
+        assert_eq!(paths("*/"), vec!["logs/"]);
+        assert_eq!(paths("logs/old/../[12].log"), vec!["logs/old/../1.log", "logs/old/../2.log"]);
+        assert_eq!(paths("*.none"), Vec::<String>::new());
+        assert_eq!(paths("a.txt/*"), Vec::<String>::new());
+
+        let nodes = glob(&shell.root, &shell.current, "**/0.log");
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].borrow().0.name(), "0.log");
+    }
+}
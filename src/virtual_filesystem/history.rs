@@ -0,0 +1,322 @@
+use std::collections::VecDeque;
+use crate::virtual_filesystem_core::graph::NodeMap;
+use crate::virtual_filesystem_core::filesystem::{FileNodePointer, FileType, Data};
+use crate::virtual_filesystem::shell::CommandError;
+
+
+const DEFAULT_LIMIT: usize = 16;
+const DEFAULT_COMMAND_LIMIT: usize = 500;
+
+
+pub type Version = usize;
+
+
+#[derive(Debug, Clone, Default)]
+struct FileHistory {
+    current: Version,
+    versions: VecDeque<(Version, Data)>,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct History {
+    limit: usize,
+    files: NodeMap<FileType, FileHistory>,
+}
+
+
+impl Default for History {
+    fn default() -> History {
+        History::new(DEFAULT_LIMIT)
+    }
+}
+
+
+impl History {
+    pub fn new(limit: usize) -> History {
+        History { limit, files: NodeMap::default() }
+    }
+
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        for history in self.files.values_mut() {
+            while history.versions.len() > limit { history.versions.pop_front(); }
+        }
+    }
+
+    pub fn record(&mut self, file: &FileNodePointer, previous: Data) {
+        if self.files.get(file).is_none() { self.files.insert(file, FileHistory::default()); }
+        let limit = self.limit;
+        if let Some(history) = self.files.get_mut(file) {
+            history.versions.push_back((history.current, previous));
+            history.current += 1;
+            while history.versions.len() > limit { history.versions.pop_front(); }
+        }
+    }
+
+    // drops the version recorded last when the change is undone
+    pub fn unrecord(&mut self, file: &FileNodePointer) {
+        if let Some(history) = self.files.get_mut(file) {
+            if history.versions.back().map(|x| x.0 + 1) == Some(history.current) { history.versions.pop_back(); }
+            history.current = history.current.saturating_sub(1);
+        }
+    }
+
+    // a file copied up from a lower layer continues the history of the original
+    pub fn copy(&mut self, from: &FileNodePointer, to: &FileNodePointer) {
+        if let Some(history) = self.files.get(from).cloned() { self.files.insert(to, history); }
+    }
+
+    pub fn prune(&mut self) {
+        self.files.prune();
+    }
+
+    pub fn versions(&self, file: &FileNodePointer, data: &str) -> Vec<(Version, Data)> {
+        match self.files.get(file) {
+            Some(history) => {
+                let mut result: Vec<(Version, Data)> = history.versions.iter().cloned().collect();
+                result.push((history.current, data.to_string()));
+                result
+            },
+            None => vec![(0, data.to_string())],
+        }
+    }
+
+    pub fn version(&self, file: &FileNodePointer, data: &str, version: Version) -> Option<Data> {
+        self.versions(file, data).into_iter()
+            .find(|x| x.0 == version)
+            .map(|x| x.1)
+    }
+}
+
+
+// the lines entered into the shell, numbered from 1 like in bash
+#[derive(Debug, Clone)]
+pub struct CommandHistory {
+    limit: usize,
+    first: usize,
+    lines: VecDeque<String>,
+}
+
+
+impl Default for CommandHistory {
+    fn default() -> CommandHistory {
+        CommandHistory::new(DEFAULT_COMMAND_LIMIT)
+    }
+}
+
+
+impl CommandHistory {
+    pub fn new(limit: usize) -> CommandHistory {
+        CommandHistory { limit, first: 1, lines: VecDeque::new() }
+    }
+
+    pub fn push(&mut self, line: &str) {
+        if line.trim().is_empty() { return }
+        self.lines.push_back(line.to_string());
+        while self.lines.len() > self.limit {
+            self.lines.pop_front();
+            self.first += 1;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.first += self.lines.len();
+        self.lines.clear();
+    }
+
+    pub fn entries(&self) -> Vec<(usize, &str)> {
+        self.lines.iter().enumerate().map(|(i, x)| (self.first + i, x.as_str())).collect()
+    }
+
+    pub fn get(&self, number: usize) -> Option<&str> {
+        let index = number.checked_sub(self.first)?;
+        self.lines.get(index).map(|x| x.as_str())
+    }
+
+    fn find<P: Fn(&str) -> bool>(&self, predicate: P) -> Option<&str> {
+        self.lines.iter().rev().find(|x| predicate(x)).map(|x| x.as_str())
+    }
+
+    // resolves the event after `!`, returning the line and the length of the designator
+    fn event(&self, designator: &[char]) -> Result<Option<(&str, usize)>, CommandError> {
+        let length = |from: usize, f: &dyn Fn(char) -> bool| from + designator[from..].iter().take_while(|c| f(**c)).count();
+        let line = match designator.first() {
+            None => return Ok(None),
+            Some(c) if c.is_whitespace() || *c == '=' || *c == '(' => return Ok(None),
+            Some('!') => self.lines.back().map(|x| (x.as_str(), 1)),
+            Some('?') => {
+                let end = length(1, &|c| c != '?' && c != '\n');
+                let text: String = designator[1..end].iter().collect();
+                let close = if designator.get(end) == Some(&'?') { 1 } else { 0 };
+                self.find(|x| x.contains(&text)).map(|x| (x, end + close))
+            },
+            Some(c) if c.is_ascii_digit() || *c == '-' => {
+                let end = length(1, &|c| c.is_ascii_digit());
+                let number: String = designator[..end].iter().collect();
+                let number = number.parse::<i64>().map_err(|_| CommandError::IllegalArgument)?;
+                let number = if number < 0 { self.first as i64 + self.lines.len() as i64 + number } else { number };
+                if number < 1 { None } else { self.get(number as usize).map(|x| (x, end)) }
+            },
+            Some(_) => {
+                let end = length(0, &|c| !c.is_whitespace() && !";|&()<>'\"".contains(c));
+                // a `!` closed by a quote is kept
+                if end == 0 { return Ok(None); }
+                let prefix: String = designator[..end].iter().collect();
+                self.find(|x| x.starts_with(&prefix)).map(|x| (x, end))
+            },
+        };
+        line.map(Some).ok_or(CommandError::NotFound)
+    }
+
+    // replaces `!!`, `!n`, `!-n`, `!prefix` and `!?text?` with lines from the
+    // history, except in single quotes and in `$((…))`
+    pub fn expand(&self, line: &str) -> Result<String, CommandError> {
+        let chars: Vec<char> = line.chars().collect();
+        let mut result = String::new();
+        let mut quoted = false;
+        let mut double = false;
+        // the open parentheses of an arithmetic expansion
+        let mut arithmetic = 0;
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            i += 1;
+            match c {
+                '\'' if !double => quoted = !quoted,
+                '"' if !quoted => double = !double,
+                '(' if !quoted && arithmetic > 0 => arithmetic += 1,
+                '(' if !quoted && result.ends_with("$(") => arithmetic = 2,
+                ')' if !quoted && arithmetic > 0 => arithmetic -= 1,
+                '\\' if !quoted => {
+                    result.push(c);
+                    if i < chars.len() {
+                        result.push(chars[i]);
+                        i += 1;
+                    }
+                    continue
+                },
+                '!' if !quoted && arithmetic == 0 && !result.ends_with('$') => {
+                    if let Some((event, length)) = self.event(&chars[i..])? {
+                        result.push_str(event);
+                        i += length;
+                        continue
+                    }
+                },
+                _ => {},
+            }
+            result.push(c);
+        }
+        Ok(result)
+    }
+}
+
+
+pub fn diff_lines(before: &str, after: &str) -> Vec<String> {
+    let a: Vec<&str> = before.lines().collect();
+    let b: Vec<&str> = after.lines().collect();
+
+    // longest common subsequence of lines
+    let mut table = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            table[i][j] = if a[i] == b[j] { table[i + 1][j + 1] + 1 }
+                else { table[i + 1][j].max(table[i][j + 1]) };
+        }
+    }
+
+    let mut result = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            result.push(format!(" {}", a[i]));
+            i += 1;
+            j += 1;
+        } else if table[i + 1][j] >= table[i][j + 1] {
+            result.push(format!("-{}", a[i]));
+            i += 1;
+        } else {
+            result.push(format!("+{}", b[j]));
+            j += 1;
+        }
+    }
+    a[i..].iter().for_each(|x| result.push(format!("-{}", x)));
+    b[j..].iter().for_each(|x| result.push(format!("+{}", x)));
+    result
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::virtual_filesystem_core::filesystem::FileNode;
+    use crate::virtual_filesystem::shell::CommandError;
+    use crate::virtual_filesystem::history::{History, CommandHistory, diff_lines};
+
+    #[test]
+    fn test_history() {
+        let file = &FileNode::create_file("file".to_string(), "".to_string(), vec![]).to_pointer();
+        let other = &FileNode::create_file("other".to_string(), "".to_string(), vec![]).to_pointer();
+        let history = &mut History::new(2);
+
+        assert_eq!(history.versions(file, "a"), vec![(0, "a".to_string())]);
+
+        history.record(file, "a".to_string());
+        history.record(file, "ab".to_string());
+        history.record(file, "abc".to_string());
+        assert_eq!(history.versions(file, "abcd"), vec![
+            (1, "ab".to_string()),
+            (2, "abc".to_string()),
+            (3, "abcd".to_string()),
+        ]);
+        assert_eq!(history.version(file, "abcd", 2), Some("abc".to_string()));
+        assert_eq!(history.version(file, "abcd", 0), None);
+        assert_eq!(history.versions(other, ""), vec![(0, "".to_string())]);
+
+        history.set_limit(1);
+        assert_eq!(history.versions(file, "abcd"), vec![(2, "abc".to_string()), (3, "abcd".to_string())]);
+
+        history.unrecord(file);
+        assert_eq!(history.versions(file, "abc"), vec![(2, "abc".to_string())]);
+
+        history.copy(file, other);
+        history.record(other, "abc".to_string());
+        assert_eq!(history.versions(other, "x"), vec![(2, "abc".to_string()), (3, "x".to_string())]);
+        assert_eq!(history.versions(file, "abc"), vec![(2, "abc".to_string())]);
+    }
+
+    #[test]
+    fn test_command_history() {
+        let history = &mut CommandHistory::new(3);
+        history.push("mkdir dir");
+        history.push("  ");
+        history.push("cd dir");
+        history.push("touch file");
+        history.push("ls");
+        assert_eq!(history.entries(), vec![(2, "cd dir"), (3, "touch file"), (4, "ls")]);
+        assert_eq!(history.get(1), None);
+        assert_eq!(history.get(3), Some("touch file"));
+
+        assert_eq!(history.expand("!!"), Ok("ls".to_string()));
+        assert_eq!(history.expand("!2; !-2"), Ok("cd dir; touch file".to_string()));
+        assert_eq!(history.expand("!to | !?di?"), Ok("touch file | cd dir".to_string()));
+        assert_eq!(history.expand("echo !c"), Ok("echo cd dir".to_string()));
+        assert_eq!(history.expand("[ ! a != b ] && echo hi!"), Ok("[ ! a != b ] && echo hi!".to_string()));
+        assert_eq!(history.expand("echo '!!' \\!! \"!!\""), Ok("echo '!!' \\!! \"ls\"".to_string()));
+        assert_eq!(history.expand("echo \"it's !c\" $((!c + (1))) !!"), Ok("echo \"it's cd dir\" $((!c + (1))) ls".to_string()));
+        assert_eq!(history.expand("echo \"hi!\" 'it\"s !!'"), Ok("echo \"hi!\" 'it\"s !!'".to_string()));
+        assert_eq!(history.expand("!1"), Err(CommandError::NotFound));
+        assert_eq!(history.expand("!none"), Err(CommandError::NotFound));
+
+        history.clear();
+        history.push("pwd");
+        assert_eq!(history.entries(), vec![(5, "pwd")]);
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        assert_eq!(diff_lines("", ""), Vec::<String>::new());
+        assert_eq!(diff_lines("a\nb\nc", "a\nc\nd"), vec![" a", "-b", " c", "+d"]);
+        assert_eq!(diff_lines("a", ""), vec!["-a"]);
+        assert_eq!(diff_lines("", "a"), vec!["+a"]);
+    }
+}
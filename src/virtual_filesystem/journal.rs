@@ -0,0 +1,203 @@
+use crate::virtual_filesystem_core::graph::Edge;
+use crate::virtual_filesystem_core::filesystem::{FileNodePointer, FileType, Data};
+use crate::virtual_filesystem::persistence::Record;
+
+
+#[derive(Debug, Clone)]
+pub enum Change {
+    Edge {
+        directory: FileNodePointer,
+        before: Edge<FileType>,
+        after: Edge<FileType>,
+    },
+    Data {
+        file: FileNodePointer,
+        before: Data,
+        after: Data,
+    },
+    // the record persisting a change and the records reverting it, so that
+    // undo and redo are persisted without writing the whole tree
+    Log {
+        record: Record,
+        inverse: Vec<Record>,
+    },
+}
+
+
+pub type Entry = Vec<Change>;
+
+
+impl Change {
+    pub fn edge(directory: &FileNodePointer, before: Edge<FileType>) -> Change {
+        Change::Edge {
+            directory: directory.clone(),
+            before,
+            after: directory.borrow().1.clone(),
+        }
+    }
+
+    pub fn data(file: &FileNodePointer, before: Data) -> Change {
+        let after = if let FileType::File{ name: _, data } = &file.borrow().0 {
+            data.to_string()
+        } else { before.to_string() };
+        Change::Data {
+            file: file.clone(),
+            before,
+            after,
+        }
+    }
+
+    pub fn log(record: Record, inverse: Vec<Record>) -> Change {
+        Change::Log {
+            record,
+            inverse,
+        }
+    }
+
+    fn apply(&self, undo: bool) {
+        match self {
+            Change::Edge{ directory, before, after } => {
+                directory.borrow_mut().1 = if undo { before.clone() } else { after.clone() };
+            },
+            Change::Data{ file, before, after } => {
+                if let FileType::File{ name: _, data } = &mut file.borrow_mut().0 {
+                    *data = if undo { before.to_string() } else { after.to_string() };
+                }
+            },
+            Change::Log{ .. } => {},
+        }
+    }
+}
+
+
+#[derive(Debug, Default, Clone)]
+pub struct Journal {
+    undo: Vec<Entry>,
+    redo: Vec<Entry>,
+    transactions: Vec<usize>,
+}
+
+
+impl Journal {
+    pub fn new() -> Journal {
+        Journal::default()
+    }
+
+    pub fn record(&mut self, entry: Entry) {
+        if entry.is_empty() { return }
+        self.undo.push(entry);
+        self.redo.clear();
+    }
+
+    pub fn undo(&mut self) -> bool {
+        if self.undo.len() <= self.mark() { return false }
+        if let Some(entry) = self.undo.pop() {
+            entry.iter().rev().for_each(|x| x.apply(true));
+            self.redo.push(entry);
+            true
+        } else { false }
+    }
+
+    pub fn redo(&mut self) -> bool {
+        if let Some(entry) = self.redo.pop() {
+            entry.iter().for_each(|x| x.apply(false));
+            self.undo.push(entry);
+            true
+        } else { false }
+    }
+
+    // the entry undone last
+    pub fn undone(&self) -> Option<&Entry> {
+        self.redo.last()
+    }
+
+    // the entry redone last
+    pub fn redone(&self) -> Option<&Entry> {
+        self.undo.last()
+    }
+
+    // the entries of the innermost open transaction
+    pub fn uncommitted(&self) -> &[Entry] {
+        if self.in_transaction() { &self.undo[self.mark()..] } else { &[] }
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        !self.transactions.is_empty()
+    }
+
+    pub fn begin(&mut self) {
+        self.transactions.push(self.undo.len());
+        self.redo.clear();
+    }
+
+    pub fn commit(&mut self) -> bool {
+        if let Some(mark) = self.transactions.pop() {
+            // a committed transaction is undone as a whole
+            let entry: Entry = self.undo.drain(mark..).flatten().collect();
+            self.record(entry);
+            true
+        } else { false }
+    }
+
+    pub fn rollback(&mut self) -> bool {
+        if let Some(mark) = self.transactions.pop() {
+            for entry in self.undo.drain(mark..).rev() {
+                entry.iter().rev().for_each(|x| x.apply(true));
+            }
+            self.redo.clear();
+            true
+        } else { false }
+    }
+
+    fn mark(&self) -> usize {
+        self.transactions.last().copied().unwrap_or(0)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::virtual_filesystem_core::filesystem::FileNode;
+    use crate::virtual_filesystem::command::{ls, mkdir, touch, read, write, find};
+    use crate::virtual_filesystem::journal::{Change, Journal};
+
+    #[test]
+    fn test_journal() {
+        let root = &FileNode::create_directory("".to_string(), vec![]).to_pointer();
+        root.borrow_mut().1.push(root.clone());
+        let journal = &mut Journal::new();
+
+        let before = root.borrow().1.clone();
+        mkdir(root, "dir".to_string());
+        journal.record(vec![Change::edge(root, before)]);
+
+        let before = root.borrow().1.clone();
+        touch(root, "file".to_string(), "".to_string());
+        journal.record(vec![Change::edge(root, before)]);
+        assert_eq!(ls(root), "dir\tfile");
+
+        let file = &find(root, "file").unwrap();
+        assert_eq!(write(file, "123"), Ok(()));
+        journal.record(vec![Change::data(file, "".to_string())]);
+
+        assert!(journal.undo());
+        assert_eq!(read(file), Ok("".to_string()));
+        assert!(journal.undo());
+        assert_eq!(ls(root), "dir");
+        assert!(journal.redo());
+        assert_eq!(ls(root), "dir\tfile");
+        assert!(journal.redo());
+        assert_eq!(read(file), Ok("123".to_string()));
+        assert!(!journal.redo());
+
+        journal.begin();
+        let before = root.borrow().1.clone();
+        mkdir(root, "tmp".to_string());
+        journal.record(vec![Change::edge(root, before)]);
+        assert_eq!(ls(root), "dir\tfile\ttmp");
+        assert!(journal.rollback());
+        assert_eq!(ls(root), "dir\tfile");
+        assert!(!journal.rollback());
+        assert!(!journal.commit());
+    }
+}
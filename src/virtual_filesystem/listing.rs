@@ -0,0 +1,212 @@
+use crate::virtual_filesystem_core::graph::NodeMap;
+use crate::virtual_filesystem_core::filesystem::{FileNodePointer, FileType, FileObject};
+use crate::virtual_filesystem::command::children;
+
+
+const TAB: usize = 8;
+const SECONDS_PER_DAY: u64 = 86400;
+
+
+// seconds since the unix epoch
+pub type Time = u64;
+pub type Clock = fn() -> Time;
+
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn system_time() -> Time {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_secs()).unwrap_or_default()
+}
+
+
+// the standard library has no clock on wasm, the frontend sets one
+#[cfg(target_arch = "wasm32")]
+pub fn system_time() -> Time {
+    0
+}
+
+
+// the modification times of the nodes changed by this shell
+#[derive(Debug, Clone, Default)]
+pub struct Times {
+    nodes: NodeMap<FileType, Time>,
+}
+
+
+impl Times {
+    pub fn record(&mut self, node: &FileNodePointer, time: Time) {
+        self.nodes.insert(node, time);
+    }
+
+    pub fn get(&self, node: &FileNodePointer) -> Option<Time> {
+        self.nodes.get(node).copied()
+    }
+
+    pub fn prune(&mut self) {
+        self.nodes.prune();
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Order {
+    Name,
+    Size,
+    Time,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub name: String,
+    pub node: FileNodePointer,
+    pub directory: bool,
+    pub writable: bool,
+    // the length of the data of a file or the number of entries of a directory
+    pub size: usize,
+    pub modified: Option<Time>,
+}
+
+
+impl Entry {
+    pub fn permissions(&self) -> &'static str {
+        match (self.directory, self.writable) {
+            (true, true) => "drwxr-xr-x",
+            (true, false) => "dr-xr-xr-x",
+            (false, true) => "-rw-r--r--",
+            (false, false) => "-r--r--r--",
+        }
+    }
+}
+
+
+// ties are broken by name so that the order does not depend on creation
+pub fn sort(entries: &mut [Entry], order: Order, reverse: bool) {
+    entries.sort_by(|a, b| {
+        let by_name = a.name.cmp(&b.name);
+        match order {
+            Order::Name => by_name,
+            Order::Size => b.size.cmp(&a.size).then(by_name),
+            Order::Time => b.modified.cmp(&a.modified).then(by_name),
+        }
+    });
+    if reverse { entries.reverse(); }
+}
+
+
+// YYYY-MM-DD HH:MM in UTC
+pub fn format_time(time: Time) -> String {
+    let days = (time / SECONDS_PER_DAY) as i64;
+    let seconds = time % SECONDS_PER_DAY;
+
+    // civil date from the days since 1970-01-01 in eras of 400 years
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, seconds / 3600, seconds % 3600 / 60)
+}
+
+
+pub fn long(entries: &[Entry]) -> String {
+    let width = entries.iter().map(|x| x.size.to_string().len()).max().unwrap_or_default();
+    entries.iter()
+        .map(|x| {
+            let time = x.modified.map(format_time).unwrap_or_else(|| "-".to_string());
+            format!("{} {:>width$} {:>16} {}", x.permissions(), x.size, time, x.name, width = width)
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+
+// the names down the columns separated by tabs so that the lines fit in `width`
+pub fn columns(names: &[String], width: usize) -> String {
+    let longest = names.iter().map(|x| x.chars().count()).max().unwrap_or_default();
+    let column = (longest / TAB + 1) * TAB;
+    let count = (width / column).max(1);
+    let rows = names.len().div_ceil(count);
+
+    let mut lines = vec![];
+    for row in 0..rows {
+        let mut line = String::new();
+        for index in (row..names.len()).step_by(rows) {
+            line.push_str(&names[index]);
+            if index + rows < names.len() {
+                let tabs = (column - names[index].chars().count()).div_ceil(TAB);
+                line.push_str(&"\t".repeat(tabs));
+            }
+        }
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+
+// the lines below a directory drawn with box-drawing connectors, the depth
+// limits the number of levels
+pub fn tree(directory: &FileNodePointer, depth: Option<usize>) -> (Vec<String>, usize, usize) {
+    let mut lines = vec![];
+    let mut counts = (0, 0);
+    branch(directory, "", depth, &mut lines, &mut counts);
+    (lines, counts.0, counts.1)
+}
+
+
+fn branch(directory: &FileNodePointer, prefix: &str, depth: Option<usize>, lines: &mut Vec<String>, counts: &mut (usize, usize)) {
+    if depth == Some(0) { return }
+
+    let mut nodes: Vec<FileNodePointer> = children(directory).into_iter()
+        .filter(|x| !x.borrow().0.name().starts_with('.'))
+        .collect();
+    nodes.sort_by(|a, b| a.borrow().0.name().cmp(b.borrow().0.name()));
+
+    for (index, node) in nodes.iter().enumerate() {
+        let last = index + 1 == nodes.len();
+        lines.push(format!("{}{}{}", prefix, if last { "└── " } else { "├── " }, node.borrow().0.name()));
+        if node.borrow().0.is_directory() {
+            counts.0 += 1;
+            let prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+            branch(node, &prefix, depth.map(|x| x - 1), lines, counts);
+        } else {
+            counts.1 += 1;
+        }
+    }
+}
+
+
+pub fn summary(directories: usize, files: usize) -> String {
+    let plural = |count: usize, one: &str, many: &str| format!("{} {}", count, if count == 1 { one } else { many });
+    format!("{}, {}", plural(directories, "directory", "directories"), plural(files, "file", "files"))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::virtual_filesystem::listing::{format_time, columns};
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(0), "1970-01-01 00:00");
+        assert_eq!(format_time(951_825_600), "2000-02-29 12:00");
+        assert_eq!(format_time(1_700_000_000), "2023-11-14 22:13");
+    }
+
+    #[test]
+    fn test_columns() {
+        let names: Vec<String> = vec!["a", "b", "c"].into_iter().map(String::from).collect();
+        assert_eq!(columns(&names, 80), "a\tb\tc");
+        assert_eq!(columns(&names, 16), "a\tc\nb");
+        assert_eq!(columns(&names, 4), "a\nb\nc");
+
+        let names: Vec<String> = vec!["readme.md", "a", "src"].into_iter().map(String::from).collect();
+        assert_eq!(columns(&names, 80), "readme.md\ta\t\tsrc");
+        assert_eq!(columns(&[], 80), "");
+    }
+}
@@ -1,2 +1,13 @@
+pub mod arithmetic;
+pub mod builtin;
 pub mod command;
-pub mod shell;
\ No newline at end of file
+pub mod completion;
+pub mod glob;
+pub mod history;
+pub mod journal;
+pub mod listing;
+pub mod parser;
+pub mod persistence;
+pub mod registry;
+pub mod shell;
+pub mod store;
\ No newline at end of file
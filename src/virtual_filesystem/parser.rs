@@ -0,0 +1,680 @@
+use crate::virtual_filesystem::shell::CommandError;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Part {
+    // unquoted text
+    Text(String),
+    // quoted or escaped text, never expanded
+    Quoted(String),
+    Variable(String),
+    // the output of `$(...)`, split into words unless quoted
+    Command {
+        source: String,
+        quoted: bool,
+    },
+    Arithmetic(String),
+}
+
+
+pub type Word = Vec<Part>;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Word(Word),
+    Operator(String),
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Connector {
+    Sequence,
+    And,
+    Or,
+}
+
+
+pub type List = Vec<(Connector, Command)>;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    // words and redirections of a single command
+    Simple(Vec<Token>),
+    Pipeline(Vec<Command>),
+    Group(List),
+    If {
+        branches: Vec<(List, List)>,
+        otherwise: Option<List>,
+    },
+    For {
+        name: String,
+        // iterates over the positional parameters when omitted
+        words: Option<Vec<Word>>,
+        body: List,
+    },
+    While {
+        condition: List,
+        body: List,
+        until: bool,
+    },
+    Function {
+        name: String,
+        body: List,
+    },
+}
+
+
+const OPERATORS: [&str; 9] = [">>", ">", "<", "||", "|", "&&", ";", "(", ")"];
+const REDIRECTIONS: [&str; 3] = [">>", ">", "<"];
+const KEYWORDS: [&str; 14] = ["if", "then", "elif", "else", "fi", "for", "in", "do", "done", "while", "until", "function", "{", "}"];
+const SPECIAL_PARAMETERS: [char; 4] = ['?', '@', '#', '*'];
+// compound commands nested deeper fail instead of overflowing the stack
+const MAX_NESTING: usize = 64;
+
+
+fn push(word: &mut Word, c: char, quoted: bool) {
+    match (word.last_mut(), quoted) {
+        (Some(Part::Text(text)), false) | (Some(Part::Quoted(text)), true) => text.push(c),
+        (_, false) => word.push(Part::Text(c.to_string())),
+        (_, true) => word.push(Part::Quoted(c.to_string())),
+    }
+}
+
+
+pub fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        _ => false,
+    }
+}
+
+
+// reads the source of `$(...)` up to the matching parenthesis
+fn substitution<I: Iterator<Item = char>>(iter: &mut std::iter::Peekable<I>) -> Result<String, CommandError> {
+    let mut source = String::new();
+    let mut depth = 1;
+    let mut quote = None;
+    while let Some(mut c) = iter.next() {
+        match (c, quote) {
+            ('\\', _) => {
+                source.push(c);
+                c = iter.next().ok_or(CommandError::IllegalArgument)?;
+            },
+            ('\'', None) | ('"', None) => { quote = Some(c); },
+            (c, Some(q)) if c == q => { quote = None; },
+            ('(', None) => { depth += 1; },
+            (')', None) => {
+                depth -= 1;
+                if depth == 0 { return Ok(source); }
+            },
+            _ => {},
+        }
+        source.push(c);
+    }
+    Err(CommandError::IllegalArgument)
+}
+
+
+// reads the expression of `$((...))` up to the closing `))`
+fn arithmetic<I: Iterator<Item = char>>(iter: &mut std::iter::Peekable<I>) -> Result<String, CommandError> {
+    let mut expression = String::new();
+    let mut depth = 0;
+    while let Some(c) = iter.next() {
+        match c {
+            '(' => { depth += 1; },
+            ')' if depth == 0 => {
+                if iter.next() != Some(')') { return Err(CommandError::IllegalArgument); }
+                return Ok(expression);
+            },
+            ')' => { depth -= 1; },
+            _ => {},
+        }
+        expression.push(c);
+    }
+    Err(CommandError::IllegalArgument)
+}
+
+
+fn variable<I: Iterator<Item = char>>(iter: &mut std::iter::Peekable<I>, quoted: bool) -> Result<Option<Part>, CommandError> {
+    match iter.peek() {
+        Some('(') => {
+            iter.next();
+            if iter.peek() == Some(&'(') {
+                iter.next();
+                return Ok(Some(Part::Arithmetic(arithmetic(iter)?)));
+            }
+            Ok(Some(Part::Command { source: substitution(iter)?, quoted }))
+        },
+        Some(c) if SPECIAL_PARAMETERS.contains(c) || c.is_ascii_digit() => {
+            let name = c.to_string();
+            iter.next();
+            Ok(Some(Part::Variable(name)))
+        },
+        Some('{') => {
+            iter.next();
+            let mut name = String::new();
+            loop {
+                match iter.next() {
+                    Some('}') => break,
+                    Some(c) => name.push(c),
+                    None => return Err(CommandError::IllegalArgument),
+                }
+            }
+            let special = name.len() == 1 && name.chars().all(|c| SPECIAL_PARAMETERS.contains(&c));
+            let positional = !name.is_empty() && name.chars().all(|c| c.is_ascii_digit());
+            if !special && !positional && !is_name(&name) { return Err(CommandError::IllegalArgument); }
+            Ok(Some(Part::Variable(name)))
+        },
+        Some(c) if c.is_ascii_alphabetic() || *c == '_' => {
+            let mut name = String::new();
+            while let Some(c) = iter.peek() {
+                if !c.is_ascii_alphanumeric() && *c != '_' { break }
+                name.push(*c);
+                iter.next();
+            }
+            Ok(Some(Part::Variable(name)))
+        },
+        _ => Ok(None),
+    }
+}
+
+
+pub fn tokenize(line: &str) -> Result<Vec<Token>, CommandError> {
+    let mut tokens = vec![];
+    // an empty quoted argument is a word with an empty part
+    let mut word: Word = vec![];
+    let mut iter = line.chars().peekable();
+
+    while let Some(c) = iter.next() {
+        match c {
+            '\n' => {
+                if !word.is_empty() { tokens.push(Token::Word(std::mem::take(&mut word))); }
+                tokens.push(Token::Operator("\n".to_string()));
+            },
+            '#' if word.is_empty() => {
+                while let Some(c) = iter.peek() {
+                    if *c == '\n' { break }
+                    iter.next();
+                }
+            },
+            c if c.is_whitespace() => {
+                if !word.is_empty() { tokens.push(Token::Word(std::mem::take(&mut word))); }
+            },
+            c if OPERATORS.iter().any(|x| x.starts_with(c)) => {
+                if !word.is_empty() { tokens.push(Token::Word(std::mem::take(&mut word))); }
+                let mut operator = c.to_string();
+                while let Some(next) = iter.peek() {
+                    let candidate = format!("{}{}", operator, next);
+                    if !OPERATORS.iter().any(|x| x.starts_with(&candidate)) { break }
+                    operator = candidate;
+                    iter.next();
+                }
+                if !OPERATORS.contains(&operator.as_str()) { return Err(CommandError::IllegalArgument); }
+                tokens.push(Token::Operator(operator));
+            },
+            '\\' => {
+                match iter.next() {
+                    Some('\n') => {},
+                    Some(c) => push(&mut word, c, true),
+                    None => return Err(CommandError::IllegalArgument),
+                }
+            },
+            '$' => {
+                match variable(&mut iter, false)? {
+                    Some(part) => word.push(part),
+                    None => push(&mut word, c, false),
+                }
+            },
+            '\'' => {
+                word.push(Part::Quoted(String::new()));
+                loop {
+                    match iter.next() {
+                        Some('\'') => break,
+                        Some(c) => push(&mut word, c, true),
+                        None => return Err(CommandError::IllegalArgument),
+                    }
+                }
+            },
+            '"' => {
+                word.push(Part::Quoted(String::new()));
+                loop {
+                    match iter.next() {
+                        Some('"') => break,
+                        Some('\\') => {
+                            match iter.next() {
+                                Some(c) if c == '"' || c == '\\' || c == '$' || c == '`' => push(&mut word, c, true),
+                                Some('\n') => {},
+                                Some(c) => { push(&mut word, '\\', true); push(&mut word, c, true); },
+                                None => return Err(CommandError::IllegalArgument),
+                            }
+                        },
+                        Some('$') => {
+                            match variable(&mut iter, true)? {
+                                Some(part) => word.push(part),
+                                None => push(&mut word, '$', true),
+                            }
+                        },
+                        Some(c) => push(&mut word, c, true),
+                        None => return Err(CommandError::IllegalArgument),
+                    }
+                }
+            },
+            c => push(&mut word, c, false),
+        }
+    }
+    if !word.is_empty() { tokens.push(Token::Word(word)); }
+
+    Ok(tokens)
+}
+
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    depth: usize,
+}
+
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn operator(&self) -> Option<&'a str> {
+        match self.peek() {
+            Some(Token::Operator(operator)) => Some(operator),
+            _ => None,
+        }
+    }
+
+    // reserved words are only recognized unquoted and in command position
+    fn keyword(&self) -> Option<&'a str> {
+        match self.peek() {
+            Some(Token::Word(word)) => match word.as_slice() {
+                [Part::Text(text)] if KEYWORDS.contains(&text.as_str()) => Some(text),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.operator() == Some("\n") { self.position += 1; }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), CommandError> {
+        if self.keyword() != Some(keyword) { return Err(CommandError::IllegalArgument); }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn expect_operator(&mut self, operator: &str) -> Result<(), CommandError> {
+        if self.operator() != Some(operator) { return Err(CommandError::IllegalArgument); }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn name(&mut self) -> Result<String, CommandError> {
+        match self.peek() {
+            Some(Token::Word(word)) => match word.as_slice() {
+                [Part::Text(text)] if is_name(text) => {
+                    self.position += 1;
+                    Ok(text.to_string())
+                },
+                _ => Err(CommandError::IllegalArgument),
+            },
+            _ => Err(CommandError::IllegalArgument),
+        }
+    }
+
+    fn list(&mut self, terminators: &[&str]) -> Result<List, CommandError> {
+        let mut list = vec![];
+        let mut connector = Connector::Sequence;
+        loop {
+            self.skip_newlines();
+            let end = match self.keyword() {
+                Some(keyword) => terminators.contains(&keyword),
+                None => self.peek().is_none(),
+            };
+            if end {
+                // `&&` and `||` need a command on their right
+                if connector != Connector::Sequence { return Err(CommandError::IllegalArgument); }
+                break;
+            }
+
+            list.push((connector, self.pipeline()?));
+            connector = match self.operator() {
+                Some(";") | Some("\n") => Connector::Sequence,
+                Some("&&") => Connector::And,
+                Some("||") => Connector::Or,
+                None if self.peek().is_none() => break,
+                _ => return Err(CommandError::IllegalArgument),
+            };
+            self.position += 1;
+        }
+        Ok(list)
+    }
+
+    fn body(&mut self, terminators: &[&str]) -> Result<List, CommandError> {
+        let list = self.list(terminators)?;
+        if list.is_empty() { return Err(CommandError::IllegalArgument); }
+        Ok(list)
+    }
+
+    fn pipeline(&mut self) -> Result<Command, CommandError> {
+        let mut stages = vec![self.command()?];
+        while self.operator() == Some("|") {
+            self.position += 1;
+            self.skip_newlines();
+            stages.push(self.command()?);
+        }
+        if stages.len() == 1 { Ok(stages.remove(0)) } else { Ok(Command::Pipeline(stages)) }
+    }
+
+    fn command(&mut self) -> Result<Command, CommandError> {
+        if self.depth >= MAX_NESTING { return Err(CommandError::IllegalArgument); }
+        self.depth += 1;
+        let command = self.nested();
+        self.depth -= 1;
+        command
+    }
+
+    fn nested(&mut self) -> Result<Command, CommandError> {
+        match self.keyword() {
+            Some("if") => self.conditional(),
+            Some("for") => self.for_loop(),
+            Some("while") | Some("until") => self.while_loop(),
+            Some("function") => {
+                self.position += 1;
+                let name = self.name()?;
+                if self.operator() == Some("(") {
+                    self.position += 1;
+                    self.expect_operator(")")?;
+                }
+                self.function(name)
+            },
+            Some("{") => Ok(Command::Group(self.group()?)),
+            Some(_) => Err(CommandError::IllegalArgument),
+            None => {
+                let definition = matches!(self.tokens.get(self.position + 1), Some(Token::Operator(x)) if x == "(");
+                if definition {
+                    let name = self.name()?;
+                    self.position += 1;
+                    self.expect_operator(")")?;
+                    self.function(name)
+                } else {
+                    self.simple()
+                }
+            },
+        }
+    }
+
+    fn simple(&mut self) -> Result<Command, CommandError> {
+        let mut tokens = vec![];
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Word(_) => { tokens.push(token.clone()); },
+                Token::Operator(operator) if REDIRECTIONS.contains(&operator.as_str()) => {
+                    tokens.push(token.clone());
+                    self.position += 1;
+                    match self.peek() {
+                        Some(target @ Token::Word(_)) => { tokens.push(target.clone()); },
+                        _ => return Err(CommandError::IllegalArgument),
+                    }
+                },
+                Token::Operator(_) => break,
+            }
+            self.position += 1;
+        }
+        if tokens.is_empty() { return Err(CommandError::IllegalArgument); }
+        Ok(Command::Simple(tokens))
+    }
+
+    fn group(&mut self) -> Result<List, CommandError> {
+        self.expect_keyword("{")?;
+        let body = self.body(&["}"])?;
+        self.expect_keyword("}")?;
+        Ok(body)
+    }
+
+    fn function(&mut self, name: String) -> Result<Command, CommandError> {
+        self.skip_newlines();
+        Ok(Command::Function { name, body: self.group()? })
+    }
+
+    fn conditional(&mut self) -> Result<Command, CommandError> {
+        let mut branches = vec![];
+        let mut otherwise = None;
+        let mut keyword = "if";
+        while keyword == "if" || keyword == "elif" {
+            self.position += 1;
+            let condition = self.body(&["then"])?;
+            self.expect_keyword("then")?;
+            let body = self.body(&["elif", "else", "fi"])?;
+            branches.push((condition, body));
+            keyword = self.keyword().unwrap_or_default();
+        }
+        if keyword == "else" {
+            self.position += 1;
+            otherwise = Some(self.body(&["fi"])?);
+        }
+        self.expect_keyword("fi")?;
+        Ok(Command::If { branches, otherwise })
+    }
+
+    fn for_loop(&mut self) -> Result<Command, CommandError> {
+        self.position += 1;
+        let name = self.name()?;
+        let mut words = None;
+        if self.keyword() == Some("in") {
+            self.position += 1;
+            let mut list = vec![];
+            while let Some(Token::Word(word)) = self.peek() {
+                list.push(word.clone());
+                self.position += 1;
+            }
+            words = Some(list);
+        }
+        if self.operator() == Some(";") { self.position += 1; }
+        self.skip_newlines();
+        self.expect_keyword("do")?;
+        let body = self.body(&["done"])?;
+        self.expect_keyword("done")?;
+        Ok(Command::For { name, words, body })
+    }
+
+    fn while_loop(&mut self) -> Result<Command, CommandError> {
+        let until = self.keyword() == Some("until");
+        self.position += 1;
+        let condition = self.body(&["do"])?;
+        self.expect_keyword("do")?;
+        let body = self.body(&["done"])?;
+        self.expect_keyword("done")?;
+        Ok(Command::While { condition, body, until })
+    }
+}
+
+
+pub fn parse(tokens: &[Token]) -> Result<List, CommandError> {
+    let mut parser = Parser { tokens, position: 0, depth: 0 };
+    let list = parser.list(&[])?;
+    // a reserved word that closes nothing
+    if parser.peek().is_some() { return Err(CommandError::IllegalArgument); }
+    Ok(list)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::virtual_filesystem::shell::CommandError;
+    use crate::virtual_filesystem::parser::{Part, Word, Token, Connector, Command, List, tokenize, parse};
+
+    fn words(line: &str) -> Result<Vec<String>, CommandError> {
+        Ok(tokenize(line)?.into_iter().map(|x| match x {
+            Token::Word(word) => word.iter().map(|x| match x {
+                Part::Text(text) | Part::Quoted(text) => text.to_string(),
+                Part::Variable(name) => format!("${{{}}}", name),
+                Part::Command{ source, quoted: false } => format!("$({})", source),
+                Part::Command{ source, quoted: true } => format!("\"$({})\"", source),
+                Part::Arithmetic(expression) => format!("$(({}))", expression),
+            }).collect(),
+            Token::Operator(operator) => format!("<{}>", operator),
+        }).collect())
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(words(""), Ok(vec![]));
+        assert_eq!(words("   "), Ok(vec![]));
+        assert_eq!(words("ls"), Ok(vec!["ls".to_string()]));
+        assert_eq!(words("  write  a\t b  "), Ok(vec!["write".to_string(), "a".to_string(), "b".to_string()]));
+        assert_eq!(words("touch 'my file'"), Ok(vec!["touch".to_string(), "my file".to_string()]));
+        assert_eq!(words("touch \"my file\""), Ok(vec!["touch".to_string(), "my file".to_string()]));
+        assert_eq!(words("touch my\\ file"), Ok(vec!["touch".to_string(), "my file".to_string()]));
+        assert_eq!(words("a'b'\"c\"d"), Ok(vec!["abcd".to_string()]));
+        assert_eq!(words("a '' \"\""), Ok(vec!["a".to_string(), "".to_string(), "".to_string()]));
+        assert_eq!(words("'a\\b' \"a\\\"b\\\\c\\d\""), Ok(vec!["a\\b".to_string(), "a\"b\\c\\d".to_string()]));
+        assert_eq!(words("\\'a"), Ok(vec!["'a".to_string()]));
+        assert_eq!(words("'a"), Err(CommandError::IllegalArgument));
+        assert_eq!(words("\"a"), Err(CommandError::IllegalArgument));
+        assert_eq!(words("a\\"), Err(CommandError::IllegalArgument));
+    }
+
+    #[test]
+    fn test_operator() {
+        assert_eq!(tokenize("ls > a"), Ok(vec![
+            Token::Word(vec![Part::Text("ls".to_string())]),
+            Token::Operator(">".to_string()),
+            Token::Word(vec![Part::Text("a".to_string())]),
+        ]));
+        assert_eq!(tokenize("a'*'\\?"), Ok(vec![
+            Token::Word(vec![Part::Text("a".to_string()), Part::Quoted("*?".to_string())]),
+        ]));
+        assert_eq!(words("ls>>a<b"), Ok(vec!["ls".to_string(), "<>>>".to_string(), "a".to_string(), "<<>".to_string(), "b".to_string()]));
+        assert_eq!(words("ls '>' \\> \">>\""), Ok(vec!["ls".to_string(), ">".to_string(), ">".to_string(), ">>".to_string()]));
+        assert_eq!(words("ls|grep a | wc"), Ok(vec![
+            "ls".to_string(), "<|>".to_string(), "grep".to_string(), "a".to_string(), "<|>".to_string(), "wc".to_string(),
+        ]));
+        assert_eq!(words("ls > > a"), Ok(vec!["ls".to_string(), "<>>".to_string(), "<>>".to_string(), "a".to_string()]));
+        assert_eq!(words("a;b && c||d"), Ok(vec![
+            "a".to_string(), "<;>".to_string(), "b".to_string(), "<&&>".to_string(), "c".to_string(), "<||>".to_string(), "d".to_string(),
+        ]));
+        assert_eq!(words("a & b"), Err(CommandError::IllegalArgument));
+        assert_eq!(words("f()"), Ok(vec!["f".to_string(), "<(>".to_string(), "<)>".to_string()]));
+        assert_eq!(words("a\nb # c d\n#e\nf#g \\\nh"), Ok(vec![
+            "a".to_string(), "<\n>".to_string(), "b".to_string(), "<\n>".to_string(), "<\n>".to_string(), "f#g".to_string(), "h".to_string(),
+        ]));
+        assert_eq!(words("'a\nb'"), Ok(vec!["a\nb".to_string()]));
+    }
+
+    #[test]
+    fn test_parse() {
+        fn word(word: &Word) -> String {
+            word.iter().map(|x| match x {
+                Part::Text(text) | Part::Quoted(text) => text.to_string(),
+                Part::Variable(name) => format!("${{{}}}", name),
+                Part::Command{ source, .. } => format!("$({})", source),
+                Part::Arithmetic(expression) => format!("$(({}))", expression),
+            }).collect()
+        }
+        fn list(list: &List) -> String {
+            list.iter().enumerate().map(|(i, (connector, x))| {
+                let connector = match (i, connector) {
+                    (0, _) => "",
+                    (_, Connector::Sequence) => "; ",
+                    (_, Connector::And) => " && ",
+                    (_, Connector::Or) => " || ",
+                };
+                format!("{}{}", connector, command(x))
+            }).collect()
+        }
+        fn command(node: &Command) -> String {
+            match node {
+                Command::Simple(tokens) => tokens.iter().map(|x| match x {
+                    Token::Word(x) => word(x),
+                    Token::Operator(x) => x.to_string(),
+                }).collect::<Vec<String>>().join(" "),
+                Command::Pipeline(stages) => stages.iter().map(command).collect::<Vec<String>>().join(" | "),
+                Command::Group(body) => format!("{{{}}}", list(body)),
+                Command::If{ branches, otherwise } => {
+                    let mut result = branches.iter()
+                        .map(|(x, y)| format!("if({}){{{}}}", list(x), list(y)))
+                        .collect::<Vec<String>>()
+                        .join("el");
+                    if let Some(x) = otherwise { result += &format!("else{{{}}}", list(x)); }
+                    result
+                },
+                Command::For{ name, words, body } => {
+                    let words = words.as_ref().map(|x| x.iter().map(word).collect::<Vec<String>>().join(" "));
+                    format!("for({} in {}){{{}}}", name, words.unwrap_or_else(|| "$@".to_string()), list(body))
+                },
+                Command::While{ condition, body, until } => {
+                    format!("{}({}){{{}}}", if *until { "until" } else { "while" }, list(condition), list(body))
+                },
+                Command::Function{ name, body } => format!("{}(){{{}}}", name, list(body)),
+            }
+        }
+        let parsed = |line: &str| -> Result<String, CommandError> { Ok(list(&parse(&tokenize(line)?)?)) };
+
+        assert_eq!(parsed(""), Ok("".to_string()));
+        assert_eq!(parsed("\n\n"), Ok("".to_string()));
+        assert_eq!(parsed("a b; c && d || e f > g;"), Ok("a b; c && d || e f > g".to_string()));
+        assert_eq!(parsed("a\n\nb &&\nc | d |\n e"), Ok("a; b && c | d | e".to_string()));
+        assert_eq!(parsed("echo if then fi"), Ok("echo if then fi".to_string()));
+        assert_eq!(parsed("if a; then b; elif c\nthen d\nelse e; f; fi"), Ok("if(a){b}elif(c){d}else{e; f}".to_string()));
+        assert_eq!(parsed("for x in a 'b c'; do echo $x; done"), Ok("for(x in a b c){echo ${x}}".to_string()));
+        assert_eq!(parsed("for x\ndo\n echo $x\ndone | sort"), Ok("for(x in $@){echo ${x}} | sort".to_string()));
+        assert_eq!(parsed("while a; do b; done; until c; do d; done"), Ok("while(a){b}; until(c){d}".to_string()));
+        assert_eq!(parsed("f() { echo $1; }\nfunction g {\n f\n}"), Ok("f(){echo ${1}}; g(){f}".to_string()));
+        assert_eq!(parsed("{ a; b; } && c"), Ok("{a; b} && c".to_string()));
+        assert_eq!(parsed("'if' a; \"fi\""), Ok("if a; fi".to_string()));
+
+        assert_eq!(parsed("; a"), Err(CommandError::IllegalArgument));
+        assert_eq!(parsed("a ;; b"), Err(CommandError::IllegalArgument));
+        assert_eq!(parsed("a ||"), Err(CommandError::IllegalArgument));
+        assert_eq!(parsed("a\n&& b"), Err(CommandError::IllegalArgument));
+        assert_eq!(parsed("a |"), Err(CommandError::IllegalArgument));
+        assert_eq!(parsed("a >"), Err(CommandError::IllegalArgument));
+        assert_eq!(parsed("if a; then b"), Err(CommandError::IllegalArgument));
+        assert_eq!(parsed("if a; then b fi"), Err(CommandError::IllegalArgument));
+        assert_eq!(parsed("if a; then fi"), Err(CommandError::IllegalArgument));
+        assert_eq!(parsed("if a; then b; fi c"), Err(CommandError::IllegalArgument));
+        assert_eq!(parsed("for 1 in a; do b; done"), Err(CommandError::IllegalArgument));
+        assert_eq!(parsed("while a; do b; end"), Err(CommandError::IllegalArgument));
+        assert_eq!(parsed("done"), Err(CommandError::IllegalArgument));
+        assert_eq!(parsed("f() echo"), Err(CommandError::IllegalArgument));
+        assert_eq!(parsed("(a)"), Err(CommandError::IllegalArgument));
+
+        // deep nesting fails instead of overflowing the stack
+        let nested = |depth: usize| format!("{}a{}", "{ ".repeat(depth), "; }".repeat(depth));
+        assert_eq!(parsed(&nested(3)), Ok("{{{a}}}".to_string()));
+        assert!(parsed(&nested(32)).is_ok());
+        assert_eq!(parsed(&nested(20_000)), Err(CommandError::IllegalArgument));
+    }
+
+    #[test]
+    fn test_variable() {
+        assert_eq!(tokenize("$?"), Ok(vec![Token::Word(vec![Part::Variable("?".to_string())])]));
+        assert_eq!(words("a$? \\$? '$?' \"$?\""), Ok(vec![
+            "a${?}".to_string(), "$?".to_string(), "$?".to_string(), "${?}".to_string(),
+        ]));
+        assert_eq!(words("$ a$"), Ok(vec!["$".to_string(), "a$".to_string()]));
+        assert_eq!(words("$HOME/a ${USER}s \"$_x-1\" $12 ${12} $@ $# $*"), Ok(vec![
+            "${HOME}/a".to_string(), "${USER}s".to_string(), "${_x}-1".to_string(), "${1}2".to_string(), "${12}".to_string(),
+            "${@}".to_string(), "${#}".to_string(), "${*}".to_string(),
+        ]));
+        assert_eq!(words("${HOME"), Err(CommandError::IllegalArgument));
+        assert_eq!(words("a$(read b) \"$(ls | wc)\""), Ok(vec!["a$(read b)".to_string(), "\"$(ls | wc)\"".to_string()]));
+        assert_eq!(words("$(echo $(pwd) ')' \"(\" \\))x"), Ok(vec!["$(echo $(pwd) ')' \"(\" \\))x".to_string()]));
+        assert_eq!(words("$((1 + (2 * $x)))"), Ok(vec!["$((1 + (2 * $x)))".to_string()]));
+        assert_eq!(words("$( (a) )"), Ok(vec!["$( (a) )".to_string()]));
+        assert_eq!(words("'$(a)'"), Ok(vec!["$(a)".to_string()]));
+        assert_eq!(words("$(a"), Err(CommandError::IllegalArgument));
+        assert_eq!(words("$((a)"), Err(CommandError::IllegalArgument));
+        assert_eq!(words("${}"), Err(CommandError::IllegalArgument));
+        assert_eq!(words("${a b}"), Err(CommandError::IllegalArgument));
+    }
+}
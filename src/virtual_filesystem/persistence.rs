@@ -0,0 +1,677 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use crate::virtual_filesystem_core::graph::{NodePointer, Graph};
+use crate::virtual_filesystem_core::filesystem::{FileNode, FileNodePointer, FileType, FileObject, Data};
+use crate::virtual_filesystem::command::{children, materialize, mkdir, touch, write, overwrite, read, find, copy_up, rm};
+use crate::virtual_filesystem::shell::CommandError;
+
+
+const IMAGE_HEADER: &str = "vfs";
+const DEFAULT_INTERVAL: usize = 64;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Record {
+    Mkdir(String),
+    Touch(String),
+    Write(String, Data),
+    Overwrite(String, Data),
+    Rm(String),
+}
+
+
+#[derive(Debug, Clone)]
+pub struct Persistence {
+    image: PathBuf,
+    journal: PathBuf,
+    interval: usize,
+    sequence: u64,
+    pending: usize,
+}
+
+
+fn escape(s: &str) -> String {
+    let mut result = String::new();
+    for c in s.chars() {
+        match c {
+            '\\' => result.push_str("\\\\"),
+            '\t' => result.push_str("\\t"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+
+fn unescape(s: &str) -> String {
+    let mut result = String::new();
+    let mut iter = s.chars();
+    while let Some(c) = iter.next() {
+        if c != '\\' { result.push(c); continue }
+        match iter.next() {
+            Some('t') => result.push('\t'),
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some(c) => result.push(c),
+            None => {},
+        }
+    }
+    result
+}
+
+
+// names are escaped in the paths of records so that a name containing `/`
+// stays a single component
+fn escape_name(name: &str) -> String {
+    name.replace('\\', "\\\\").replace('/', "\\/")
+}
+
+
+fn components(path: &str) -> Vec<String> {
+    let mut result = vec![];
+    let mut component = String::new();
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => { if let Some(c) = chars.next() { component.push(c); } },
+            '/' => {
+                if !component.is_empty() { result.push(component); }
+                component = String::new();
+            },
+            c => component.push(c),
+        }
+    }
+    if !component.is_empty() { result.push(component); }
+    result
+}
+
+
+fn join(path: &str, name: &str) -> String {
+    format!("{}/{}", path, escape_name(name))
+}
+
+
+// the path of `name` in `directory` as it is written in records
+pub fn path(directory: &FileNodePointer, name: &str) -> String {
+    let mut names = vec![];
+    let mut position = directory.clone();
+    loop {
+        let parent = match position.borrow().1.first() {
+            Some(parent) if !NodePointer::ptr_eq(parent, &position) => parent.clone(),
+            _ => break,
+        };
+        names.push(position.borrow().0.name().to_string());
+        position = parent;
+    }
+    let directory = names.iter().rev().fold(String::new(), |path, x| join(&path, x));
+    join(&directory, name)
+}
+
+
+fn locate(root: &FileNodePointer, path: &str) -> Result<(FileNodePointer, String), CommandError> {
+    let mut names = components(path);
+    let name = names.pop().ok_or(CommandError::IllegalArgument)?;
+    let mut directory = root.clone();
+    for x in &names {
+        directory = find(&directory, x)?;
+    }
+    Ok((materialize(&directory).0, name))
+}
+
+
+// the records creating a node and everything below it at `path`
+pub fn create(node: &FileNodePointer, path: &str) -> Vec<Record> {
+    fn walk(node: &FileNodePointer, path: &str, result: &mut Vec<Record>) {
+        match &node.borrow().0 {
+            FileType::File{ name: _, data } => {
+                result.push(Record::Touch(path.to_string()));
+                if !data.is_empty() { result.push(Record::Overwrite(path.to_string(), data.to_string())); }
+            },
+            x if x.is_directory() => {
+                result.push(Record::Mkdir(path.to_string()));
+                for child in children(node) {
+                    walk(&child, &join(path, child.borrow().0.name()), result);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    let mut result = vec![];
+    walk(node, path, &mut result);
+    result
+}
+
+
+// the records turning the tree `from` into the tree `to`, the subtrees shared
+// between both sides are skipped
+pub fn changes(from: &FileNodePointer, to: &FileNodePointer) -> Vec<Record> {
+    fn walk(from: &FileNodePointer, to: &FileNodePointer, path: &str, result: &mut Vec<Record>) {
+        if NodePointer::ptr_eq(from, to) { return }
+
+        let before = children(from);
+        let after = children(to);
+        let name = |x: &FileNodePointer| x.borrow().0.name().to_string();
+
+        for b in &before {
+            if !after.iter().any(|a| name(a) == name(b)) {
+                result.push(Record::Rm(join(path, &name(b))));
+            }
+        }
+        for a in &after {
+            let child = join(path, &name(a));
+            match before.iter().find(|b| name(b) == name(a)) {
+                None => { result.extend(create(a, &child)); },
+                Some(b) => {
+                    match (b.borrow().0.is_directory(), a.borrow().0.is_directory()) {
+                        (true, true) => { walk(b, a, &child, result); },
+                        (false, false) => {
+                            if let Ok(data) = read(a) {
+                                if read(b).as_ref() != Ok(&data) { result.push(Record::Overwrite(child, data)); }
+                            }
+                        },
+                        _ => {
+                            result.push(Record::Rm(child.to_string()));
+                            result.extend(create(a, &child));
+                        },
+                    }
+                },
+            }
+        }
+    }
+
+    let mut result = vec![];
+    walk(from, to, "", &mut result);
+    result
+}
+
+
+fn checksum(s: &str) -> u32 {
+    // FNV-1a
+    s.bytes().fold(0x811c_9dc5, |hash: u32, x| (hash ^ x as u32).wrapping_mul(0x0100_0193))
+}
+
+
+pub fn serialize(root: &FileNodePointer) -> String {
+    fn walk(directory: &FileNodePointer, result: &mut String) {
+        for node in children(directory) {
+            match &node.borrow().0 {
+                FileType::File{ name, data } => {
+                    result.push_str(&format!("f\t{}\t{}\n", escape(name), escape(data)));
+                },
+                x if x.is_directory() => {
+                    result.push_str(&format!("d\t{}\n", escape(x.name())));
+                    walk(&node, result);
+                    result.push_str(".\n");
+                },
+                _ => {},
+            }
+        }
+    }
+
+    let mut result = String::new();
+    walk(root, &mut result);
+    result
+}
+
+
+pub fn deserialize(image: &str) -> Option<FileNodePointer> {
+    let root = FileNode::create_directory("".to_string(), vec![]).to_pointer();
+    root.borrow_mut().connect(root.clone());
+
+    let mut stack = vec![root.clone()];
+    for line in image.lines() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let directory = stack.last()?.clone();
+        match fields.as_slice() {
+            ["d", name] => {
+                mkdir(&directory, unescape(name));
+                stack.push(directory.borrow().1.last()?.clone());
+            },
+            ["f", name, data] => {
+                touch(&directory, unescape(name), unescape(data));
+            },
+            ["."] => {
+                stack.pop();
+                if stack.is_empty() { return None }
+            },
+            _ => return None,
+        }
+    }
+
+    Some(root)
+}
+
+
+impl Record {
+    fn encode(&self) -> Vec<String> {
+        match self {
+            Record::Mkdir(path) => vec!["mkdir".to_string(), escape(path)],
+            Record::Touch(path) => vec!["touch".to_string(), escape(path)],
+            Record::Write(path, data) => vec!["write".to_string(), escape(path), escape(data)],
+            Record::Overwrite(path, data) => vec!["overwrite".to_string(), escape(path), escape(data)],
+            Record::Rm(path) => vec!["rm".to_string(), escape(path)],
+        }
+    }
+
+    fn decode(fields: &[&str]) -> Option<Vec<Record>> {
+        let mut result = vec![];
+        let mut iter = fields.iter();
+        while let Some(op) = iter.next() {
+            let path = unescape(iter.next()?);
+            let record = match *op {
+                "mkdir" => Record::Mkdir(path),
+                "touch" => Record::Touch(path),
+                "write" => Record::Write(path, unescape(iter.next()?)),
+                "overwrite" => Record::Overwrite(path, unescape(iter.next()?)),
+                "rm" => Record::Rm(path),
+                _ => return None,
+            };
+            result.push(record);
+        }
+        Some(result)
+    }
+
+    pub fn apply(&self, root: &FileNodePointer) -> Result<(), CommandError> {
+        match self {
+            Record::Mkdir(path) => {
+                let (directory, name) = locate(root, path)?;
+                mkdir(&directory, name);
+            },
+            Record::Touch(path) => {
+                let (directory, name) = locate(root, path)?;
+                touch(&directory, name, "".to_string());
+            },
+            Record::Write(path, data) => {
+                let (directory, name) = locate(root, path)?;
+                let file = find(&directory, &name)?;
+                write(&copy_up(&directory, &file), data)?;
+            },
+            Record::Overwrite(path, data) => {
+                let (directory, name) = locate(root, path)?;
+                let file = find(&directory, &name)?;
+                overwrite(&copy_up(&directory, &file), data)?;
+            },
+            Record::Rm(path) => {
+                let (directory, name) = locate(root, path)?;
+                rm(&directory, &name)?;
+            },
+        }
+        Ok(())
+    }
+}
+
+
+impl Persistence {
+    pub fn new<P: AsRef<Path>>(path: P) -> Persistence {
+        let image = path.as_ref().to_path_buf();
+        let mut journal = image.clone().into_os_string();
+        journal.push(".journal");
+        Persistence {
+            image,
+            journal: PathBuf::from(journal),
+            interval: DEFAULT_INTERVAL,
+            sequence: 0,
+            pending: 0,
+        }
+    }
+
+    pub fn with_interval(mut self, interval: usize) -> Persistence {
+        self.interval = interval;
+        self
+    }
+
+    pub fn load(&mut self) -> io::Result<FileNodePointer> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "broken image");
+
+        let (sequence, root) = match fs::read_to_string(&self.image) {
+            Ok(image) => {
+                let mut lines = image.splitn(2, '\n');
+                let header: Vec<&str> = lines.next().unwrap_or("").split('\t').collect();
+                let sequence = match header.as_slice() {
+                    [IMAGE_HEADER, sequence] => sequence.parse::<u64>().map_err(|_| invalid())?,
+                    _ => return Err(invalid()),
+                };
+                let root = deserialize(lines.next().unwrap_or("")).ok_or_else(invalid)?;
+                (sequence, root)
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let root = FileNode::create_directory("".to_string(), vec![]).to_pointer();
+                root.borrow_mut().connect(root.clone());
+                (0, root)
+            },
+            Err(e) => return Err(e),
+        };
+        self.sequence = sequence;
+
+        let mut journal = vec![];
+        match File::open(&self.journal) {
+            Ok(mut file) => { file.read_to_end(&mut journal)?; },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => return Err(e),
+        }
+
+        // a torn entry at the end is left by a crash while appending and is
+        // dropped. an intact entry which can not be replayed means that the
+        // image and the journal do not belong together, which is reported.
+        for line in journal.split_inclusive(|x| *x == b'\n') {
+            let line = match std::str::from_utf8(line) {
+                Ok(line) if line.ends_with('\n') => &line[..line.len() - 1],
+                _ => break,
+            };
+            let (body, sum) = match line.rfind('\t') {
+                Some(i) => (&line[..i], &line[i + 1..]),
+                None => break,
+            };
+            if u32::from_str_radix(sum, 16).ok() != Some(checksum(body)) { break }
+
+            let fields: Vec<&str> = body.split('\t').collect();
+            let sequence = match fields.first().and_then(|x| x.parse::<u64>().ok()) {
+                Some(sequence) => sequence,
+                None => break,
+            };
+            if sequence <= self.sequence { continue }
+            let broken = |reason: &str| io::Error::new(io::ErrorKind::InvalidData, format!("journal entry {}: {}", sequence, reason));
+            if sequence != self.sequence + 1 { return Err(broken("out of sequence")) }
+            let records = Record::decode(&fields[1..]).ok_or_else(|| broken("unknown record"))?;
+            for record in &records {
+                record.apply(&root).map_err(|e| broken(&e.to_string()))?;
+            }
+            self.sequence = sequence;
+        }
+
+        self.checkpoint(&root)?;
+        Ok(root)
+    }
+
+    pub fn append(&mut self, records: &[Record], root: &FileNodePointer) -> io::Result<()> {
+        if records.is_empty() { return Ok(()) }
+
+        let mut fields = vec![(self.sequence + 1).to_string()];
+        records.iter().for_each(|x| fields.extend(x.encode()));
+        let body = fields.join("\t");
+        let line = format!("{}\t{:08x}\n", body, checksum(&body));
+
+        // a partial write is cut off so that the next entry starts a line
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.journal)?;
+        let length = file.metadata()?.len();
+        if let Err(e) = file.write_all(line.as_bytes()).and_then(|_| file.sync_data()) {
+            let _ = file.set_len(length);
+            return Err(e);
+        }
+        self.sequence += 1;
+        self.pending += 1;
+
+        if self.pending >= self.interval { self.checkpoint(root)?; }
+        Ok(())
+    }
+
+    pub fn checkpoint(&mut self, root: &FileNodePointer) -> io::Result<()> {
+        let mut temporary = self.image.clone().into_os_string();
+        temporary.push(".tmp");
+        let temporary = PathBuf::from(temporary);
+
+        {
+            let mut file = File::create(&temporary)?;
+            file.write_all(format!("{}\t{}\n", IMAGE_HEADER, self.sequence).as_bytes())?;
+            file.write_all(serialize(root).as_bytes())?;
+            file.sync_all()?;
+        }
+        fs::rename(&temporary, &self.image)?;
+
+        // entries up to the image sequence are skipped on replay, so a crash
+        // before the journal is truncated is harmless.
+        File::create(&self.journal)?.sync_all()?;
+        self.pending = 0;
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use crate::virtual_filesystem::shell::{DefaultLoggerRepository, Shell};
+    use crate::virtual_filesystem::persistence::{Persistence, Record, serialize, deserialize, checksum};
+
+    fn temporary(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("virtual-filesystem-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn test_serialize() {
+        let shell = &mut Shell::init();
+        assert_eq!(shell.run("mkdir dir"), Ok(None));
+        assert_eq!(shell.run("touch file"), Ok(None));
+        assert_eq!(shell.run("write file 'a\tb\\c'"), Ok(None));
+        assert_eq!(shell.run("cd dir"), Ok(None));
+        assert_eq!(shell.run("touch nested"), Ok(None));
+
+        let image = serialize(&shell.root);
+        assert_eq!(image, "d\tdir\nf\tnested\t\n.\nf\tfile\ta\\tb\\\\c\n");
+
+        let root = deserialize(&image).unwrap();
+        assert_eq!(serialize(&root), image);
+
+        assert_eq!(deserialize("d\tdir\n.\n.\n"), None);
+        assert_eq!(deserialize("x\n"), None);
+    }
+
+    #[test]
+    fn test_record() {
+        let shell = &mut Shell::init();
+        let root = &shell.root;
+
+        assert_eq!(Record::Mkdir("/dir".to_string()).apply(root), Ok(()));
+        assert_eq!(Record::Touch("/dir/file".to_string()).apply(root), Ok(()));
+        assert_eq!(Record::Write("/dir/file".to_string(), "123".to_string()).apply(root), Ok(()));
+        assert_eq!(Record::Overwrite("/dir/file".to_string(), "12".to_string()).apply(root), Ok(()));
+        assert_eq!(Record::Write("/dir/file".to_string(), "3".to_string()).apply(root), Ok(()));
+        assert_eq!(Record::Touch("/other".to_string()).apply(root), Ok(()));
+        assert_eq!(Record::Rm("/other".to_string()).apply(root), Ok(()));
+        assert!(Record::Rm("/other".to_string()).apply(root).is_err());
+        assert_eq!(serialize(root), "d\tdir\nf\tfile\t123\n.\n");
+
+        // a slash in a name is escaped so that it is not a separator
+        assert_eq!(Record::Mkdir("/a\\/b".to_string()).apply(root), Ok(()));
+        assert_eq!(Record::Touch("/a\\/b/c\\\\d".to_string()).apply(root), Ok(()));
+        assert_eq!(serialize(root), "d\tdir\nf\tfile\t123\n.\nd\ta/b\nf\tc\\\\d\t\n.\n");
+    }
+
+    #[test]
+    fn test_persistence() {
+        let directory = temporary("persistence");
+        let path = directory.join("image");
+
+        {
+            let shell = &mut Shell::init();
+            assert_eq!(shell.persist(Persistence::new(&path)), Ok(()));
+            assert_eq!(shell.run("mkdir dir"), Ok(None));
+            assert_eq!(shell.run("cd dir"), Ok(None));
+            assert_eq!(shell.run("touch file"), Ok(None));
+            assert_eq!(shell.run("write file 123"), Ok(None));
+        }
+
+        {
+            let shell = &mut Shell::init();
+            assert_eq!(shell.persist(Persistence::new(&path)), Ok(()));
+            assert_eq!(shell.run("ls"), Ok(Some("dir".to_string())));
+            assert_eq!(shell.run("cd dir"), Ok(None));
+            assert_eq!(shell.run("read file"), Ok(Some("123".to_string())));
+            assert_eq!(shell.run("write file 456"), Ok(None));
+            assert_eq!(shell.run("undo"), Ok(None));
+            assert_eq!(shell.run("touch other"), Ok(None));
+            assert_eq!(shell.run("rm other"), Ok(None));
+            assert_eq!(shell.run("begin"), Ok(None));
+            assert_eq!(shell.run("touch uncommitted"), Ok(None));
+        }
+
+        {
+            let shell = &mut Shell::init();
+            assert_eq!(shell.persist(Persistence::new(&path)), Ok(()));
+            assert_eq!(shell.run("cd dir"), Ok(None));
+            assert_eq!(shell.run("ls"), Ok(Some("file".to_string())));
+            assert_eq!(shell.run("read file"), Ok(Some("123".to_string())));
+        }
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn test_failed_append() {
+        let directory = temporary("append");
+        let path = directory.join("image");
+        let journal = directory.join("image.journal");
+
+        let shell = &mut Shell::init();
+        assert_eq!(shell.persist(Persistence::new(&path)), Ok(()));
+        assert_eq!(shell.run("mkdir a"), Ok(None));
+
+        // the journal can not be opened, the change is kept in memory
+        fs::remove_file(&journal).unwrap();
+        fs::create_dir(&journal).unwrap();
+        assert!(shell.run("mkdir b").is_err());
+        assert!(shell.run("mkdir c").is_err());
+
+        // the whole tree is written once the journal can be written again
+        fs::remove_dir(&journal).unwrap();
+        assert_eq!(shell.run("mkdir d"), Ok(None));
+        assert_eq!(shell.run("mkdir e"), Ok(None));
+
+        let root = Persistence::new(&path).load().unwrap();
+        assert_eq!(serialize(&root), "d\ta\n.\nd\tb\n.\nd\tc\n.\nd\td\n.\nd\te\n.\n");
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn test_replace_logger() {
+        let directory = temporary("logger");
+        let path = directory.join("image");
+
+        let shell = &mut Shell::init();
+        assert_eq!(shell.persist(Persistence::new(&path)), Ok(()));
+        assert_eq!(shell.run("mkdir kept; begin; touch uncommitted"), Ok(None));
+
+        // the open transaction and the persistence are carried over
+        let replaced = &mut shell.replace_logger(DefaultLoggerRepository{});
+        assert!(replaced.in_transaction());
+        assert_eq!(replaced.run("rollback; ls"), Ok(Some("kept".to_string())));
+        assert_eq!(replaced.run("undo; ls"), Ok(Some("".to_string())));
+        assert_eq!(replaced.run("mkdir added"), Ok(None));
+
+        let root = Persistence::new(&path).load().unwrap();
+        assert_eq!(serialize(&root), "d\tadded\n.\n");
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn test_journaled_commands() {
+        let directory = temporary("journaled");
+        let path = directory.join("image");
+
+        let state = {
+            let shell = &mut Shell::init();
+            assert_eq!(shell.persist(Persistence::new(&path)), Ok(()));
+            assert_eq!(shell.run("mkdir a/b; mkdir dir; cd dir; touch c; write c 123; cd /"), Ok(None));
+            assert_eq!(shell.run("snapshot first; touch d"), Ok(None));
+            assert_eq!(shell.run("rm -r dir; undo; redo; undo; undo"), Ok(None));
+            assert_eq!(shell.run("restore first; touch e; undo; redo"), Ok(None));
+
+            // undo, redo and restore are appended to the journal
+            assert_eq!(fs::read_to_string(&path).unwrap(), "vfs\t0\n");
+            serialize(&shell.root)
+        };
+        assert_eq!(state, "d\ta/b\n.\nd\tdir\nf\tc\t123\n.\nf\te\t\n");
+
+        let root = Persistence::new(&path).load().unwrap();
+        assert_eq!(serialize(&root), state);
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn test_broken_journal() {
+        let directory = temporary("broken");
+        let path = directory.join("image");
+
+        // an intact entry which does not apply is reported instead of dropped
+        let body = "1\trm\t/missing";
+        fs::write(&path, "vfs\t0\n").unwrap();
+        fs::write(directory.join("image.journal"), format!("{}\t{:08x}\n", body, checksum(body))).unwrap();
+        assert!(Persistence::new(&path).load().is_err());
+
+        let body = "3\tmkdir\t/a";
+        fs::write(directory.join("image.journal"), format!("{}\t{:08x}\n", body, checksum(body))).unwrap();
+        assert!(Persistence::new(&path).load().is_err());
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let directory = temporary("checkpoint");
+        let path = directory.join("image");
+
+        let shell = &mut Shell::init();
+        assert_eq!(shell.persist(Persistence::new(&path).with_interval(2)), Ok(()));
+        assert_eq!(shell.run("mkdir a"), Ok(None));
+        assert_eq!(fs::read_to_string(directory.join("image")).unwrap(), "vfs\t0\n");
+        assert_ne!(fs::read_to_string(directory.join("image.journal")).unwrap(), "");
+        assert_eq!(shell.run("mkdir b"), Ok(None));
+        assert_eq!(fs::read_to_string(directory.join("image")).unwrap(), "vfs\t2\nd\ta\n.\nd\tb\n.\n");
+        assert_eq!(fs::read_to_string(directory.join("image.journal")).unwrap(), "");
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn test_recovery() {
+        let directory = temporary("recovery");
+        let path = directory.join("image");
+
+        let commands = [
+            "mkdir dir",
+            "cd dir",
+            "touch file",
+            "write file first line",
+            "write file \u{3042}\u{3044}\u{3046}",
+            "cd ..",
+            "touch other",
+            "rm -r dir",
+        ];
+        let mut states = vec![];
+        {
+            let shell = &mut Shell::init();
+            assert_eq!(shell.persist(Persistence::new(&path)), Ok(()));
+            states.push(serialize(&shell.root));
+            for command in commands.iter() {
+                assert_eq!(shell.run(command), Ok(None));
+                let state = serialize(&shell.root);
+                if states.last() != Some(&state) { states.push(state); }
+            }
+        }
+
+        let image = fs::read(directory.join("image")).unwrap();
+        let journal = fs::read(directory.join("image.journal")).unwrap();
+        for offset in 0..=journal.len() {
+            let recovery = directory.join(format!("recovery-{}", offset));
+            fs::write(&recovery, &image).unwrap();
+            fs::write(directory.join(format!("recovery-{}.journal", offset)), &journal[..offset]).unwrap();
+
+            let complete = journal[..offset].iter().filter(|x| **x == b'\n').count();
+            let root = Persistence::new(&recovery).load().unwrap();
+            assert_eq!(serialize(&root), states[complete], "offset {}", offset);
+        }
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+}
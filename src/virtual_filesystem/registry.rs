@@ -0,0 +1,374 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use crate::virtual_filesystem_core::logger::LoggerRepository;
+use crate::virtual_filesystem::shell::{Arg, CommandError, CommandResult, Shell};
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CommandOption {
+    pub flag: &'static str,
+    pub value: Option<&'static str>,
+    pub description: &'static str,
+}
+
+
+impl CommandOption {
+    pub const fn flag(flag: &'static str, description: &'static str) -> CommandOption {
+        CommandOption { flag, value: None, description }
+    }
+
+    pub const fn value(flag: &'static str, value: &'static str, description: &'static str) -> CommandOption {
+        CommandOption { flag, value: Some(value), description }
+    }
+}
+
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Arguments<'a> {
+    options: Vec<(&'static str, Option<&'a Arg>)>,
+    pub operands: Vec<&'a Arg>,
+    // the output goes to a file, a pipe or a substitution instead of the terminal
+    pub redirected: bool,
+}
+
+
+impl<'a> Arguments<'a> {
+    pub fn new(operands: Vec<&'a Arg>) -> Arguments<'a> {
+        Arguments { options: vec![], operands, redirected: false }
+    }
+
+    // options come before the operands and end at the first operand or `--`,
+    // a lone `-` is an operand
+    pub fn parse(options: &[CommandOption], args: &[&'a Arg]) -> Result<Arguments<'a>, CommandError> {
+        if options.is_empty() { return Ok(Arguments::new(args.to_vec())); }
+
+        let mut result = Arguments::default();
+        let mut iter = args.iter().copied().peekable();
+        while let Some(arg) = iter.peek().copied() {
+            if arg == "--" {
+                iter.next();
+                break
+            }
+            if !arg.starts_with('-') || arg == "-" { break }
+            iter.next();
+            let option = match options.iter().find(|x| x.flag == arg) {
+                Some(option) => option,
+                None => {
+                    result.options.append(&mut Arguments::combined(options, arg)?);
+                    continue
+                },
+            };
+            let value = match option.value {
+                Some(_) => Some(iter.next().ok_or(CommandError::IllegalArgument)?),
+                None => None,
+            };
+            result.options.push((option.flag, value));
+        }
+        result.operands = iter.collect();
+        Ok(result)
+    }
+
+    // single letter flags without values may be combined as in `-la`
+    fn combined(options: &[CommandOption], arg: &Arg) -> Result<Vec<(&'static str, Option<&'a Arg>)>, CommandError> {
+        if arg.starts_with("--") { return Err(CommandError::IllegalArgument); }
+        arg.chars().skip(1)
+            .map(|c| {
+                options.iter()
+                    .find(|x| x.value.is_none() && x.flag.len() == 2 && x.flag.ends_with(c))
+                    .map(|x| (x.flag, None))
+                    .ok_or(CommandError::IllegalArgument)
+            })
+            .collect()
+    }
+
+    pub fn has(&self, flag: &str) -> bool {
+        self.options.iter().any(|x| x.0 == flag)
+    }
+
+    // the value of the last occurrence of an option
+    pub fn value(&self, flag: &str) -> Option<&'a Arg> {
+        self.options.iter().rev().find(|x| x.0 == flag).and_then(|x| x.1)
+    }
+
+    pub fn operand(&self, index: usize) -> Option<&'a Arg> {
+        self.operands.get(index).copied()
+    }
+}
+
+
+// the name followed by the options and the operands
+pub fn default_usage<T: LoggerRepository, C: ShellCommand<T> + ?Sized>(command: &C) -> String {
+    let mut result = vec![command.name().to_string()];
+    for option in command.options() {
+        match option.value {
+            Some(value) => result.push(format!("[{} {}]", option.flag, value)),
+            None => result.push(format!("[{}]", option.flag)),
+        }
+    }
+    if !command.operands().is_empty() { result.push(command.operands().to_string()); }
+    result.join(" ")
+}
+
+
+pub trait ShellCommand<T: LoggerRepository> {
+    fn name(&self) -> &str;
+
+    fn description(&self) -> &str;
+
+    fn operands(&self) -> &str {
+        ""
+    }
+
+    fn options(&self) -> &[CommandOption] {
+        &[]
+    }
+
+    fn usage(&self) -> String {
+        default_usage(self)
+    }
+
+    // `--help` as the only argument shows the help of a command with options
+    fn takes_help(&self) -> bool {
+        !self.options().is_empty()
+    }
+
+    fn help(&self) -> String {
+        let mut result = vec![format!("usage: {}", self.usage()), self.description().to_string()];
+        let options: Vec<String> = self.options().iter()
+            .map(|x| match x.value {
+                Some(value) => format!("{} {}", x.flag, value),
+                None => x.flag.to_string(),
+            })
+            .collect();
+        let width = options.iter().map(|x| x.chars().count()).max().unwrap_or_default();
+        for (option, definition) in options.iter().zip(self.options()) {
+            result.push(format!("  {:width$}  {}", option, definition.description, width = width));
+        }
+        result.join("\n")
+    }
+
+    fn parse<'a>(&self, args: &[&'a Arg]) -> Result<Arguments<'a>, CommandError> {
+        Arguments::parse(self.options(), args)
+    }
+
+    fn execute(&self, shell: &mut Shell<T>, args: &Arguments, input: Option<String>) -> CommandResult;
+}
+
+
+pub type Function<T> = fn(&mut Shell<T>, &Arguments, Option<String>) -> CommandResult;
+
+
+// a command defined by a function
+pub struct Builtin<T: LoggerRepository> {
+    name: &'static str,
+    operands: &'static str,
+    description: &'static str,
+    options: &'static [CommandOption],
+    raw: bool,
+    function: Function<T>,
+}
+
+
+impl<T: LoggerRepository> Clone for Builtin<T> {
+    fn clone(&self) -> Builtin<T> {
+        Builtin { function: self.function, ..*self }
+    }
+}
+
+
+impl<T: LoggerRepository> Builtin<T> {
+    pub fn new(name: &'static str, operands: &'static str, description: &'static str, function: Function<T>) -> Builtin<T> {
+        Builtin { name, operands, description, options: &[], raw: false, function }
+    }
+
+    pub fn with_options(mut self, options: &'static [CommandOption]) -> Builtin<T> {
+        self.options = options;
+        self
+    }
+
+    // the options are only listed, every argument is passed as an operand
+    pub fn with_raw_arguments(mut self) -> Builtin<T> {
+        self.raw = true;
+        self
+    }
+}
+
+
+impl<T: LoggerRepository> ShellCommand<T> for Builtin<T> {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn description(&self) -> &str {
+        self.description
+    }
+
+    fn operands(&self) -> &str {
+        self.operands
+    }
+
+    fn options(&self) -> &[CommandOption] {
+        self.options
+    }
+
+    fn usage(&self) -> String {
+        // the options of raw arguments are operators and are shown by `help` only
+        if self.raw { format!("{} {}", self.name, self.operands) } else { default_usage(self) }
+    }
+
+    // raw arguments are passed unchanged, `--help` included
+    fn takes_help(&self) -> bool {
+        !self.raw && !self.options.is_empty()
+    }
+
+    fn parse<'a>(&self, args: &[&'a Arg]) -> Result<Arguments<'a>, CommandError> {
+        if self.raw { Ok(Arguments::new(args.to_vec())) } else { Arguments::parse(self.options, args) }
+    }
+
+    fn execute(&self, shell: &mut Shell<T>, args: &Arguments, input: Option<String>) -> CommandResult {
+        (self.function)(shell, args, input)
+    }
+}
+
+
+// a built-in command or a registered one. built-in commands are not trait
+// objects, so that a shell does not require a 'static logger
+pub enum Definition<T: LoggerRepository> {
+    Builtin(Builtin<T>),
+    Command(Rc<dyn ShellCommand<T>>),
+}
+
+
+impl<T: LoggerRepository> Clone for Definition<T> {
+    fn clone(&self) -> Definition<T> {
+        match self {
+            Definition::Builtin(x) => Definition::Builtin(x.clone()),
+            Definition::Command(x) => Definition::Command(x.clone()),
+        }
+    }
+}
+
+
+impl<T: LoggerRepository> Definition<T> {
+    fn command(&self) -> &dyn ShellCommand<T> {
+        match self {
+            Definition::Builtin(x) => x,
+            Definition::Command(x) => x.as_ref(),
+        }
+    }
+}
+
+
+impl<T: LoggerRepository> ShellCommand<T> for Definition<T> {
+    fn name(&self) -> &str {
+        self.command().name()
+    }
+
+    fn description(&self) -> &str {
+        self.command().description()
+    }
+
+    fn operands(&self) -> &str {
+        self.command().operands()
+    }
+
+    fn options(&self) -> &[CommandOption] {
+        self.command().options()
+    }
+
+    fn usage(&self) -> String {
+        self.command().usage()
+    }
+
+    fn help(&self) -> String {
+        self.command().help()
+    }
+
+    fn takes_help(&self) -> bool {
+        self.command().takes_help()
+    }
+
+    fn parse<'a>(&self, args: &[&'a Arg]) -> Result<Arguments<'a>, CommandError> {
+        self.command().parse(args)
+    }
+
+    fn execute(&self, shell: &mut Shell<T>, args: &Arguments, input: Option<String>) -> CommandResult {
+        self.command().execute(shell, args, input)
+    }
+}
+
+
+pub struct Registry<T: LoggerRepository> {
+    commands: BTreeMap<String, Definition<T>>,
+}
+
+
+impl<T: LoggerRepository> std::fmt::Debug for Registry<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_list().entries(self.commands.keys()).finish()
+    }
+}
+
+
+impl<T: LoggerRepository> Default for Registry<T> {
+    fn default() -> Registry<T> {
+        Registry { commands: BTreeMap::new() }
+    }
+}
+
+
+impl<T: LoggerRepository> Registry<T> {
+    pub fn new() -> Registry<T> {
+        Registry::default()
+    }
+
+    // a command replaces any command registered with the same name
+    pub fn register(&mut self, definition: Definition<T>) {
+        self.commands.insert(definition.name().to_string(), definition);
+    }
+
+    pub fn unregister(&mut self, name: &str) -> Option<Definition<T>> {
+        self.commands.remove(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<Definition<T>> {
+        self.commands.get(name).cloned()
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.commands.keys().map(|x| x.as_str()).collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::virtual_filesystem::shell::CommandError;
+    use crate::virtual_filesystem::registry::{Arguments, CommandOption};
+
+    const OPTIONS: [CommandOption; 2] = [
+        CommandOption::flag("-r", "reverse"),
+        CommandOption::value("-n", "lines", "number of lines"),
+    ];
+
+    #[test]
+    fn test_arguments() {
+        let args = Arguments::parse(&OPTIONS, &["-r", "-n", "3", "file", "-r"]).unwrap();
+        assert!(args.has("-r"));
+        assert_eq!(args.value("-n"), Some("3"));
+        assert_eq!(args.operands, vec!["file", "-r"]);
+
+        let args = Arguments::parse(&OPTIONS, &["--", "-r", "-"]).unwrap();
+        assert!(!args.has("-r"));
+        assert_eq!(args.operands, vec!["-r", "-"]);
+
+        let args = Arguments::parse(&OPTIONS, &["-rr", "file"]).unwrap();
+        assert!(args.has("-r"));
+        assert_eq!(args.operands, vec!["file"]);
+
+        assert_eq!(Arguments::parse(&OPTIONS, &["-x"]), Err(CommandError::IllegalArgument));
+        assert_eq!(Arguments::parse(&OPTIONS, &["-rn", "3"]), Err(CommandError::IllegalArgument));
+        assert_eq!(Arguments::parse(&OPTIONS, &["-n"]), Err(CommandError::IllegalArgument));
+        assert_eq!(Arguments::parse(&[], &["-x"]), Ok(Arguments::new(vec!["-x"])));
+    }
+}
@@ -1,21 +1,151 @@
-use crate::virtual_filesystem_core::graph::Graph;
-use crate::virtual_filesystem_core::filesystem::{FileNode, FileNodePointer, FileObject};
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use crate::virtual_filesystem_core::graph::{NodePointer, Graph};
+use crate::virtual_filesystem_core::filesystem::{FileNode, FileNodePointer, FileType, Name};
 use crate::virtual_filesystem_core::logger::{LoggerRepository, LoggerInteractor};
-use crate::virtual_filesystem::command::{ls, pwd, mkdir, touch, write, read, find};
+use crate::virtual_filesystem::command::{pwd, mount, materialize, reattach, mkdir, touch, write, overwrite, read, find, split_path, resolve, diff, copy_up, rm, children, flatten};
+use crate::virtual_filesystem::journal::{Change, Entry, Journal};
+use crate::virtual_filesystem::history::{History, CommandHistory, Version};
+use crate::virtual_filesystem::listing::{Clock, Time, Times, system_time};
+use crate::virtual_filesystem::persistence::{Persistence, Record, path, create, changes};
+use crate::virtual_filesystem::store::{ObjectId, ObjectStore};
+use crate::virtual_filesystem::arithmetic::evaluate;
+use crate::virtual_filesystem::glob::{escape, is_pattern, glob_paths};
+use crate::virtual_filesystem::builtin;
+use crate::virtual_filesystem::registry::{Definition, Registry, ShellCommand};
+use crate::virtual_filesystem::completion::{word_start, is_command_position, command_name, complete_name, complete_path};
+use crate::virtual_filesystem::parser::{Part, Word, Token, Connector, Command, List, tokenize, parse, is_name};
 
 
 pub type Buffer = String;
 pub type Arg = str;
 pub type CommandResult = Result<Option<String>, CommandError>;
+pub type ExitStatus = i32;
 
 
-#[derive(Debug, PartialEq)]
+const MAX_DEPTH: usize = 64;
+pub const RC_FILE: &str = ".vfsrc";
+
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum CommandError {
     UnknownError,
     NotFound,
     IllegalArgument,
     NotFile,
+    ReadOnly,
     CommandNotFound(String),
+    // a failure without a message, like `false`
+    Failure(ExitStatus),
+    // the output of the commands run before a failure
+    Partial(String, Box<CommandError>),
+}
+
+
+impl CommandError {
+    pub fn status(&self) -> ExitStatus {
+        match self {
+            CommandError::CommandNotFound(_) => 127,
+            CommandError::Failure(status) => *status,
+            CommandError::Partial(_, e) => e.status(),
+            _ => 1,
+        }
+    }
+
+    // keeps the output produced before the error
+    fn partial(output: String, error: CommandError) -> CommandError {
+        match error {
+            CommandError::Partial(more, e) => CommandError::Partial(format!("{}\n{}", output, more), e),
+            e => CommandError::Partial(output, Box::new(e)),
+        }
+    }
+}
+
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CommandError::UnknownError => write!(f, "unknown error."),
+            CommandError::NotFound => write!(f, "not found."),
+            CommandError::IllegalArgument => write!(f, "illegal argument."),
+            CommandError::NotFile => write!(f, "not file."),
+            CommandError::ReadOnly => write!(f, "read-only filesystem."),
+            CommandError::CommandNotFound(command) => write!(f, "{} command not found.", command),
+            CommandError::Failure(_) => Ok(()),
+            CommandError::Partial(output, e) => match e.to_string() {
+                message if message.is_empty() => write!(f, "{}", output),
+                message => write!(f, "{}\n{}", output, message),
+            },
+        }
+    }
+}
+
+
+pub fn exit_status(result: &CommandResult) -> ExitStatus {
+    match result {
+        Ok(_) => 0,
+        Err(e) => e.status(),
+    }
+}
+
+
+// joins the results of several commands into one. the last error is kept
+// with the output before it when the final status is a failure, other errors
+// are rendered as text.
+fn collect(mut results: Vec<CommandResult>, status: ExitStatus) -> CommandResult {
+    let error = if status == 0 { None } else {
+        match results.pop() {
+            Some(Err(e)) => Some(e),
+            Some(result) => {
+                results.push(result);
+                Some(CommandError::Failure(status))
+            },
+            None => Some(CommandError::Failure(status)),
+        }
+    };
+
+    let output: Vec<String> = results.into_iter()
+        .filter_map(|result| match result {
+            Ok(result) => result,
+            Err(e) => Some(e.to_string()).filter(|x| !x.is_empty()),
+        })
+        .collect();
+    match error {
+        Some(e) if output.is_empty() => Err(e),
+        Some(e) => Err(CommandError::partial(output.join("\n"), e)),
+        None if output.is_empty() => Ok(None),
+        None => Ok(Some(output.join("\n"))),
+    }
+}
+
+
+fn assignment(word: &Word) -> Option<&str> {
+    match word.first() {
+        Some(Part::Text(text)) => text.find('=').map(|i| &text[..i]).filter(|x| is_name(x)),
+        _ => None,
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Control {
+    Break,
+    Continue,
+    Return,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct Mount {
+    pub point: FileNodePointer,
+    pub readonly: bool,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub name: Name,
+    pub root: FileNodePointer,
 }
 
 
@@ -26,6 +156,37 @@ where
 {
     pub root: FileNodePointer,
     pub current: FileNodePointer,
+    pub readonly: bool,
+    pub mounts: Vec<Mount>,
+    pub snapshots: Vec<Snapshot>,
+    pub exit_status: ExitStatus,
+    // the result of the rc file run by init, for the frontend to show
+    pub rc: CommandResult,
+    // the status of the last failure not tested by `&&`, `||` or a condition
+    // since a line was run at the top level
+    pub failure: ExitStatus,
+    environment: BTreeMap<String, String>,
+    pub(crate) positional: Vec<String>,
+    functions: BTreeMap<String, List>,
+    pub(crate) aliases: BTreeMap<String, String>,
+    pub(crate) control: Option<Control>,
+    pub(crate) loops: usize,
+    pub(crate) journal: Journal,
+    history: History,
+    times: Times,
+    clock: Clock,
+    pub(crate) commands: CommandHistory,
+    history_expansion: bool,
+    store: ObjectStore,
+    persistence: Option<Persistence>,
+    records: Vec<Record>,
+    marks: Vec<usize>,
+    dirty: bool,
+    pub(crate) depth: usize,
+    captured: bool,
+    // the status of the last substitution in the command being expanded
+    substitution: Option<ExitStatus>,
+    registry: Registry<T>,
     logger: LoggerInteractor<T>,
 }
 
@@ -41,438 +202,3102 @@ impl Shell<DefaultLoggerRepository> {
     pub fn init() -> Shell<DefaultLoggerRepository> {
         Shell::init_with_logger(DefaultLoggerRepository{})
     }
+
+    #[allow(dead_code)]
+    pub fn init_overlay(lower: FileNodePointer) -> Shell<DefaultLoggerRepository> {
+        Shell::init_overlay_with_logger(lower, DefaultLoggerRepository{})
+    }
+
+    #[allow(dead_code)]
+    pub fn init_with_root(root: FileNodePointer) -> Shell<DefaultLoggerRepository> {
+        Shell::init_with_root_and_logger(root, DefaultLoggerRepository{})
+    }
 }
 
 
 impl<T: LoggerRepository> Shell<T> {
     fn new(root: FileNodePointer, current: FileNodePointer, logger: T) -> Shell<T> {
         Shell {
-            root: root,
-            current: current,
+            root,
+            current,
+            readonly: false,
+            mounts: vec![],
+            snapshots: vec![],
+            exit_status: 0,
+            rc: Ok(None),
+            failure: 0,
+            environment: BTreeMap::new(),
+            positional: vec![],
+            functions: BTreeMap::new(),
+            aliases: BTreeMap::new(),
+            control: None,
+            loops: 0,
+            journal: Journal::new(),
+            history: History::default(),
+            times: Times::default(),
+            clock: system_time,
+            commands: CommandHistory::default(),
+            history_expansion: true,
+            store: ObjectStore::new(),
+            persistence: None,
+            records: vec![],
+            marks: vec![],
+            dirty: false,
+            depth: 0,
+            captured: false,
+            substitution: None,
+            registry: builtin::registry(),
             logger: LoggerInteractor::new(logger),
         }
     }
 
     pub fn init_with_logger(logger: T) -> Shell<T> {
         let root = FileNode::create_directory("".to_string(), vec![]).to_pointer();
+        root.borrow_mut().connect(root.clone());
+        Shell::init_with_root_and_logger(root, logger)
+    }
+
+    pub fn init_overlay_with_logger(lower: FileNodePointer, logger: T) -> Shell<T> {
+        let root = FileNode::create_overlay("".to_string(), lower, vec![]).to_pointer();
+        root.borrow_mut().connect(root.clone());
+        Shell::init_with_root_and_logger(root, logger)
+    }
+
+    // the root must already be connected to itself as its own parent
+    pub fn init_with_root_and_logger(root: FileNodePointer, logger: T) -> Shell<T> {
         let current = root.clone();
-        root.borrow_mut().connect(current.clone());
-        Shell::new(root, current, logger)
+        let mut shell = Shell::new(root, current, logger);
+        shell.set_variable("HOME", "/");
+        shell.set_variable("USER", "user");
+        shell.rc = shell.load_rc();
+        if let Err(e) = &shell.rc {
+            shell.logger.print(&format!("rc error {}", e));
+        }
+        shell.load_history();
+        shell
+    }
+
+    fn load_rc(&mut self) -> CommandResult {
+        let home = self.variable("HOME").unwrap_or_else(|| "/".to_string());
+        let path = if home.ends_with('/') { format!("{}{}", home, RC_FILE) } else { format!("{}/{}", home, RC_FILE) };
+        let script = match self.read_path(&path) {
+            Ok(script) => script,
+            Err(CommandError::NotFound) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let result = self.run_script(&script);
+        // the startup commands are not part of the undo history, a transaction
+        // left open by them is kept
+        if !self.in_transaction() { self.discard_journal()?; }
+        result
     }
 
     #[allow(dead_code)]
+    // commands registered for this shell are bound to its logger and are not carried over.
+    // the new shell continues an open transaction and the persistence, which
+    // the replaced shell should no longer use.
     pub fn replace_logger<R: LoggerRepository>(&self, logger: R) -> Shell<R> {
-        Shell::new(self.root.clone(), self.current.clone(), logger)
+        let mut shell = Shell::new(self.root.clone(), self.current.clone(), logger);
+        shell.readonly = self.readonly;
+        shell.mounts = self.mounts.clone();
+        shell.snapshots = self.snapshots.clone();
+        shell.exit_status = self.exit_status;
+        shell.rc = self.rc.clone();
+        shell.failure = self.failure;
+        shell.environment = self.environment.clone();
+        shell.positional = self.positional.clone();
+        shell.functions = self.functions.clone();
+        shell.aliases = self.aliases.clone();
+        shell.control = self.control;
+        shell.loops = self.loops;
+        shell.journal = self.journal.clone();
+        shell.history = self.history.clone();
+        shell.times = self.times.clone();
+        shell.clock = self.clock;
+        shell.commands = self.commands.clone();
+        shell.history_expansion = self.history_expansion;
+        shell.store = self.store.clone();
+        shell.persistence = self.persistence.clone();
+        shell.records = self.records.clone();
+        shell.marks = self.marks.clone();
+        shell.dirty = self.dirty;
+        shell.depth = self.depth;
+        shell.captured = self.captured;
+        shell.substitution = self.substitution;
+        shell
     }
 
-    pub fn run(&mut self, buffer: &Arg) -> CommandResult {
-        self.logger.print(&format!("run arg {}", buffer));
-        let argv: Vec<&Arg> = buffer.trim()
-            .split(' ')
+    pub fn variable(&self, name: &str) -> Option<String> {
+        match name {
+            "?" => Some(self.exit_status.to_string()),
+            "#" => Some(self.positional.len().to_string()),
+            "@" | "*" => Some(self.positional.join(" ")),
+            "PWD" => Some(pwd(&self.current)),
+            _ if name.chars().all(|c| c.is_ascii_digit()) => {
+                let index = name.parse::<usize>().ok()?;
+                if index == 0 { None } else { self.positional.get(index - 1).cloned() }
+            },
+            _ => self.environment.get(name).cloned(),
+        }
+    }
+
+    pub fn set_variable(&mut self, name: &str, value: &str) {
+        self.environment.insert(name.to_string(), value.to_string());
+    }
+
+    pub fn unset_variable(&mut self, name: &str) {
+        self.environment.remove(name);
+    }
+
+    pub fn variables(&self) -> Vec<(String, String)> {
+        let mut result: Vec<(String, String)> = self.environment.iter()
+            .filter(|(name, _)| name.as_str() != "PWD")
+            .map(|(name, value)| (name.to_string(), value.to_string()))
             .collect();
-        let mut iter = argv.iter();
-
-        let command = if let Some(head) = iter.next() {
-            if head.is_empty() { return Ok(None); }
-            *head
-        } else { return Ok(None); };
-
-        if command == "ls" {
-            let current = &self.current;
-            let result = ls(current);
-            Ok(Some(result))
-        } else if command == "pwd" {
-            let current = &self.current;
-            let result = pwd(current);
-            Ok(Some(result))
-        } else if command == "cd" {
-            let change = if let Some(arg) = iter.next() {
-                let current = &self.current;
-                if arg == &"/" {
-                    Ok(self.root.clone())
-                } else if arg == &"." {
-                    Ok(current.clone())
-                } else if arg == &".." {
-                    if let Some(parent) = current.borrow().1.iter().next() {
-                        Ok(parent.clone())
-                    } else {
-                        Err(CommandError::UnknownError)
-                    }
-                } else {
-                    if let Ok(pointer) = find(current, arg) {
-                        Ok(pointer.clone())
-                    } else {
-                        Err(CommandError::NotFound)
-                    }
-                }
-            } else {
-                Err(CommandError::IllegalArgument)
+        result.push(("PWD".to_string(), pwd(&self.current)));
+        result.sort();
+        result
+    }
+
+    // returns the byte offset of the word before `cursor` and the words that can replace it
+    pub fn complete(&self, line: &str, cursor: usize) -> (usize, Vec<String>) {
+        let cursor = (0..=cursor.min(line.len())).rev().find(|x| line.is_char_boundary(*x)).unwrap_or(0);
+        let start = word_start(line, cursor);
+        let word = &line[start..cursor];
+
+        let candidates = if let Some(prefix) = word.strip_prefix('$') {
+            let variables = self.variables();
+            let names = variables.iter().map(|x| x.0.as_str());
+            complete_name(names, prefix).into_iter().map(|x| format!("${}", x)).collect()
+        } else if word.starts_with('-') && !is_command_position(&line[..start]) {
+            let definition = command_name(&line[..start]).and_then(|x| self.registry.get(x));
+            let options = definition.as_ref().map(|x| x.options()).unwrap_or_default();
+            let mut flags: Vec<&str> = options.iter().map(|x| x.flag).collect();
+            // an argument of `cd` is the previous directory
+            if definition.map(|x| x.name() == "cd").unwrap_or_default() { flags.push("-"); }
+            complete_name(flags, word)
+        } else if (is_command_position(&line[..start]) || command_name(&line[..start]) == Some("help")) && !word.contains('/') {
+            let names = self.registry.names().into_iter()
+                .chain(self.functions.keys().map(|x| x.as_str()))
+                .chain(self.aliases.keys().map(|x| x.as_str()));
+            complete_name(names, word)
+        } else {
+            complete_path(&self.root, &self.current, word)
+        };
+        (start, candidates)
+    }
+
+    // a command replaces the built-in command with the same name
+    pub fn register<C: ShellCommand<T> + 'static>(&mut self, command: C) {
+        self.registry.register(Definition::Command(Rc::new(command)));
+    }
+
+    pub fn unregister(&mut self, name: &str) -> Result<(), CommandError> {
+        self.registry.unregister(name).map(|_| ()).ok_or(CommandError::NotFound)
+    }
+
+    pub fn command(&self, name: &str) -> Option<Definition<T>> {
+        self.registry.get(name)
+    }
+
+    pub fn command_names(&self) -> Vec<&str> {
+        self.registry.names()
+    }
+
+    pub fn set_alias(&mut self, name: &str, value: &str) {
+        self.aliases.insert(name.to_string(), value.to_string());
+    }
+
+    pub fn unset_alias(&mut self, name: &str) -> Result<(), CommandError> {
+        self.aliases.remove(name).map(|_| ()).ok_or(CommandError::NotFound)
+    }
+
+    // replaces aliased words in command position before the input is parsed
+    fn expand_aliases(&self, tokens: Vec<Token>, expanded: &[&str]) -> Result<Vec<Token>, CommandError> {
+        const STARTS: [&str; 8] = ["if", "then", "elif", "else", "while", "until", "do", "{"];
+        let mut result = vec![];
+        let mut position = true;
+        let mut iter = tokens.into_iter().peekable();
+        while let Some(token) = iter.next() {
+            let word = match &token {
+                Token::Word(word) => match word.as_slice() {
+                    [Part::Text(text)] => Some(text.to_string()),
+                    _ => None,
+                },
+                Token::Operator(_) => None,
             };
-            if let Ok(change) = change {
-                self.current = change;
-                Ok(None)
-            } else {
-                Err(change.unwrap_err())
+            let definition = matches!(iter.peek(), Some(Token::Operator(x)) if x == "(");
+            match (&word, &token) {
+                (Some(name), _) if position && !definition && !expanded.contains(&name.as_str()) && self.aliases.contains_key(name) => {
+                    let mut names = expanded.to_vec();
+                    names.push(name);
+                    result.extend(self.expand_aliases(tokenize(&self.aliases[name])?, &names)?);
+                    position = false;
+                },
+                (Some(name), _) => {
+                    position = position && STARTS.contains(&name.as_str());
+                    result.push(token);
+                },
+                (None, Token::Operator(operator)) => {
+                    position = ["\n", ";", "&&", "||", "|"].contains(&operator.as_str());
+                    result.push(token);
+                },
+                (None, _) => {
+                    position = false;
+                    result.push(token);
+                },
             }
-        } else if command == "find" {
-            let current = &self.current;
-            if let Some(arg) = iter.next() {
-                if let Ok(pointer) = find(current, arg) {
-                    let node = &pointer.borrow();
-                    let file = &node.0;
-                    let name = file.name();
-                    Ok(Some(name.to_string()))
-                } else {
-                    Err(CommandError::NotFound)
-                }
-            } else {
-                Err(CommandError::IllegalArgument)
+        }
+        Ok(result)
+    }
+
+    pub fn set_readonly(&mut self, readonly: bool) {
+        self.readonly = readonly;
+    }
+
+    pub fn mount(&mut self, name: &Arg, tree: FileNodePointer, readonly: bool) -> Result<(), CommandError> {
+        self.check_writable(&self.current)?;
+        let (current, _) = self.upper(&self.current.clone());
+        mount(&current, name.to_string(), tree.clone());
+        self.mounts.push(Mount { point: tree, readonly });
+        self.checkpoint()
+    }
+
+    pub fn persist(&mut self, mut persistence: Persistence) -> Result<(), CommandError> {
+        self.discard_journal()?;
+        let root = persistence.load().map_err(|_| CommandError::UnknownError)?;
+        self.root = root.clone();
+        self.current = root;
+        self.snapshots = vec![];
+        self.persistence = Some(persistence);
+        Ok(())
+    }
+
+    pub fn checkpoint(&mut self) -> Result<(), CommandError> {
+        if self.in_transaction() {
+            self.dirty = true;
+            return Ok(());
+        }
+        if let Some(persistence) = &mut self.persistence {
+            if persistence.checkpoint(&self.root).is_err() {
+                self.dirty = true;
+                return Err(CommandError::UnknownError);
             }
-        } else if command == "mkdir" {
-            let current = &mut self.current;
-            if let Some(arg) = iter.next() {
-                mkdir(current, arg.to_string());
-                Ok(None)
-            } else {
-                Err(CommandError::IllegalArgument)
+        }
+        self.dirty = false;
+        Ok(())
+    }
+
+    // the record is persisted without an entry of the journal
+    pub fn save(&mut self, record: Record) -> Result<(), CommandError> {
+        self.save_all(vec![record])
+    }
+
+    // the records reach the journal file as a single entry
+    fn save_all(&mut self, records: Vec<Record>) -> Result<(), CommandError> {
+        if self.persistence.is_none() || records.is_empty() { return Ok(()); }
+        self.records.extend(records);
+        if self.in_transaction() { return Ok(()); }
+        self.flush()
+    }
+
+    // the entry is kept with the record persisting it, and with the records
+    // reverting it which are only built when the shell is persisted
+    pub fn record<F: FnOnce() -> Vec<Record>>(&mut self, mut entry: Entry, record: Record, inverse: F) -> Result<(), CommandError> {
+        if self.persistence.is_some() { entry.push(Change::log(record.clone(), inverse())); }
+        self.journal.record(entry);
+        self.save(record)
+    }
+
+    // the records are kept until they are written. after a failure the whole
+    // tree is written instead, since the journal may end with a torn entry.
+    fn flush(&mut self) -> Result<(), CommandError> {
+        if self.dirty {
+            self.checkpoint()?;
+            self.records.clear();
+            return Ok(());
+        }
+        if let Some(persistence) = &mut self.persistence {
+            if persistence.append(&self.records, &self.root).is_err() {
+                self.dirty = true;
+                return Err(CommandError::UnknownError);
             }
-        } else if command == "touch" {
-            let current = &mut self.current;
-            if let Some(arg) = iter.next() {
-                touch(current, arg.to_string(), "".to_string());
-                Ok(None)
-            } else {
-                Err(CommandError::IllegalArgument)
+        }
+        self.records.clear();
+        Ok(())
+    }
+
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history.set_limit(limit);
+    }
+
+    // `!` is only replaced with lines from the history when the expansion is
+    // enabled, which frontends running scripts turn off
+    pub fn set_history_expansion(&mut self, enabled: bool) {
+        self.history_expansion = enabled;
+    }
+
+    pub fn command_history(&self) -> Vec<(usize, &str)> {
+        self.commands.entries()
+    }
+
+    // reads the lines of $HISTFILE into the history
+    pub fn load_history(&mut self) {
+        let path = match self.variable("HISTFILE") {
+            Some(path) if !path.is_empty() => path,
+            _ => return,
+        };
+        if let Ok(data) = self.read_path(&path) {
+            data.lines().for_each(|x| self.commands.push(x));
+        }
+    }
+
+    fn remember(&mut self, line: &str) {
+        self.commands.push(line);
+        if line.trim().is_empty() { return }
+        if let Err(e) = self.append_history(line) {
+            self.logger.print(&format!("history error {}", e));
+        }
+    }
+
+    // the node and path of $HISTFILE and whether it was created. the file is
+    // written directly so that the journal and versions only contain changes
+    // made by commands
+    fn history_file(&mut self) -> Result<Option<(FileNodePointer, String, bool)>, CommandError> {
+        let path = match self.variable("HISTFILE") {
+            Some(path) if !path.is_empty() => path,
+            _ => return Ok(None),
+        };
+        let (parent, name) = split_path(&path);
+        let directory = resolve(&self.root, &self.current, parent)?;
+        self.check_writable(&directory)?;
+        let (directory, _) = self.upper(&directory);
+
+        let created = find(&directory, name).is_err();
+        if created {
+            touch(&directory, name.to_string(), "".to_string());
+            self.save(Record::Touch(self.path(&directory, name)))?;
+        }
+        let node = copy_up(&directory, &find(&directory, name)?);
+        Ok(Some((node, self.path(&directory, name), created)))
+    }
+
+    // a line is appended to the file, the whole history only fills a new one
+    fn append_history(&mut self, line: &str) -> Result<(), CommandError> {
+        let (node, path, created) = match self.history_file()? {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        let data = if created { self.history_data() } else { format!("{}\n", line) };
+        write(&node, &data)?;
+        self.modify(&node);
+        self.save(Record::Write(path, data))
+    }
+
+    pub(crate) fn save_history(&mut self) -> Result<(), CommandError> {
+        let (node, path, _) = match self.history_file()? {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        let data = self.history_data();
+        overwrite(&node, &data)?;
+        self.modify(&node);
+        self.save(Record::Overwrite(path, data))
+    }
+
+    fn history_data(&self) -> String {
+        self.commands.entries().iter().map(|x| format!("{}\n", x.1)).collect()
+    }
+
+    pub fn versions(&self, name: &Arg) -> Result<Vec<(Version, String)>, CommandError> {
+        let file = find(&self.current, name)?;
+        let data = read(&file)?;
+        Ok(self.history.versions(&file, &data))
+    }
+
+    pub fn checkout_version(&mut self, name: &Arg, version: Version) -> Result<(), CommandError> {
+        let pointer = find(&self.current, name)?;
+        let previous = read(&pointer)?;
+        let data = self.history.version(&pointer, &previous, version).ok_or(CommandError::NotFound)?;
+        let current = self.current.clone();
+        self.write_file(&current, name, &data, false)
+    }
+
+    // the file as it is in the last commit
+    pub fn checkout_file(&mut self, name: &Arg) -> Result<(), CommandError> {
+        let head = self.store.head.clone().ok_or(CommandError::NotFound)?;
+        let root = self.store.read_tree(&self.store.tree_of(&head)?)?;
+        let path = format!("{}/{}", pwd(&self.current), name);
+        let data = read(&resolve(&root, &root, &path)?)?;
+        let current = self.current.clone();
+        self.write_file(&current, name, &data, false)
+    }
+
+    pub fn commit_tree(&mut self, message: &Arg) -> ObjectId {
+        self.store.commit(&self.root, message)
+    }
+
+    pub fn commits(&self) -> Vec<(ObjectId, String)> {
+        self.store.log()
+    }
+
+    pub fn checkout_commit(&mut self, id: &Arg) -> Result<(), CommandError> {
+        self.check_writable(&self.root)?;
+        // the tree read from the store has no mount points, so a read-only
+        // mount would become writable
+        if self.mounts.iter().any(|x| x.readonly) { return Err(CommandError::ReadOnly); }
+        let id = self.store.lookup(id)?;
+        let tree = self.store.tree_of(&id)?;
+        let root = self.store.read_tree(&tree)?;
+        self.discard_journal()?;
+
+        let path = pwd(&self.current);
+        self.current = resolve(&root, &root, &path).unwrap_or_else(|_| root.clone());
+        self.root = root.clone();
+        self.store.head = Some(id);
+        // the nodes read from the store are written by the checkout
+        let mut nodes = vec![root];
+        while let Some(node) = nodes.pop() {
+            self.modify(&node);
+            if node.borrow().0.is_directory() { nodes.extend(children(&node)); }
+        }
+        self.checkpoint()
+    }
+
+    pub fn status(&mut self) -> Vec<String> {
+        let head = match &self.store.head {
+            Some(id) => self.store.tree_of(id).and_then(|x| self.store.read_tree(&x)),
+            None => Err(CommandError::NotFound),
+        };
+        let head = head.unwrap_or_else(|_| {
+            let root = FileNode::create_directory("".to_string(), vec![]).to_pointer();
+            root.borrow_mut().connect(root.clone());
+            root
+        });
+        diff(&head, &self.root)
+    }
+
+    pub(crate) fn version_argument(&self, name: &Arg, version: Option<&&Arg>) -> Result<String, CommandError> {
+        let versions = self.versions(name)?;
+        match version {
+            Some(version) => {
+                let version = version.parse::<Version>().map_err(|_| CommandError::IllegalArgument)?;
+                versions.into_iter().find(|x| x.0 == version).map(|x| x.1).ok_or(CommandError::NotFound)
+            },
+            None => Ok(versions.last().map(|x| x.1.to_string()).unwrap_or_default()),
+        }
+    }
+
+    pub fn path(&self, directory: &FileNodePointer, name: &Arg) -> String {
+        path(directory, name)
+    }
+
+    // the file operations below are journaled, so that commands registered
+    // from outside the crate can be undone and are persisted
+    pub fn touch_file(&mut self, directory: &FileNodePointer, name: &Arg) -> Result<(), CommandError> {
+        self.check_writable(directory)?;
+        let (directory, mut entry) = self.upper(directory);
+        let directory = &directory;
+        let before = directory.borrow().1.clone();
+        touch(directory, name.to_string(), "".to_string());
+        self.modify(directory);
+        if let Some(file) = directory.borrow().1.last() { self.modify(file); }
+        entry.push(Change::edge(directory, before));
+        let path = self.path(directory, name);
+        self.record(entry, Record::Touch(path.to_string()), || vec![Record::Rm(path)])
+    }
+
+    pub fn make_directory(&mut self, directory: &FileNodePointer, name: &Arg) -> Result<(), CommandError> {
+        self.check_writable(directory)?;
+        let (directory, mut entry) = self.upper(directory);
+        let directory = &directory;
+        let before = directory.borrow().1.clone();
+        mkdir(directory, name.to_string());
+        self.modify(directory);
+        if let Some(node) = directory.borrow().1.last() { self.modify(node); }
+        entry.push(Change::edge(directory, before));
+        let path = self.path(directory, name);
+        self.record(entry, Record::Mkdir(path.to_string()), || vec![Record::Rm(path)])
+    }
+
+    pub fn write_file(&mut self, directory: &FileNodePointer, name: &Arg, data: &str, append: bool) -> Result<(), CommandError> {
+        find(directory, name)?;
+        self.check_writable(directory)?;
+        let (directory, mut entry) = self.upper(directory);
+        let directory = &directory;
+        let pointer = find(directory, name)?;
+        let before = directory.borrow().1.clone();
+        let node = &copy_up(directory, &pointer);
+        let previous = read(node)?;
+        if append { write(node, data)?; } else { overwrite(node, data)?; }
+        if !NodePointer::ptr_eq(node, &pointer) { self.history.copy(&pointer, node); }
+        self.history.record(node, previous.to_string());
+        if !NodePointer::ptr_eq(node, &pointer) {
+            entry.push(Change::edge(directory, before));
+        }
+        entry.push(Change::data(node, previous.to_string()));
+        self.modify(node);
+        let path = self.path(directory, name);
+        let record = if append { Record::Write(path.to_string(), data.to_string()) } else { Record::Overwrite(path.to_string(), data.to_string()) };
+        self.record(entry, record, || vec![Record::Overwrite(path, previous)])
+    }
+
+    pub fn read_path(&self, path: &Arg) -> Result<String, CommandError> {
+        let (parent, name) = split_path(path);
+        let directory = resolve(&self.root, &self.current, parent)?;
+        read(&find(&directory, name)?)
+    }
+
+    // a directory with entries is only removed when `recursive` is set
+    fn removable(&self, path: &Arg, recursive: bool) -> Result<(FileNodePointer, FileNodePointer), CommandError> {
+        let (parent, name) = split_path(path);
+        let directory = resolve(&self.root, &self.current, parent)?;
+        let node = find(&directory, name)?;
+        self.check_writable(&node)?;
+        if node.borrow().0.is_directory() && !recursive && !children(&node).is_empty() {
+            return Err(CommandError::IllegalArgument);
+        }
+        Ok((directory, node))
+    }
+
+    pub fn remove_path(&mut self, path: &Arg, recursive: bool) -> Result<(), CommandError> {
+        let (directory, node) = self.removable(path, recursive)?;
+        let (directory, mut entry) = self.upper(&directory);
+        let name = split_path(path).1;
+        let before = directory.borrow().1.clone();
+        rm(&directory, name)?;
+        self.modify(&directory);
+        self.history.prune();
+        self.times.prune();
+        entry.push(Change::edge(&directory, before));
+        let path = self.path(&directory, name);
+        self.record(entry, Record::Rm(path.to_string()), || create(&node, &path))
+    }
+
+    // a directory is connected to the tree before it is modified. the entry
+    // reverts the connection.
+    fn upper(&mut self, directory: &FileNodePointer) -> (FileNodePointer, Entry) {
+        let (directory, changed) = materialize(directory);
+        let entry = changed.into_iter().map(|(x, before)| Change::edge(&x, before)).collect();
+        self.current = reattach(&self.current);
+        (directory, entry)
+    }
+
+    // every path is checked before anything is removed and the paths are
+    // removed as a single entry of the journal
+    pub fn remove_paths(&mut self, paths: &[&Arg], recursive: bool) -> Result<(), CommandError> {
+        for path in paths { self.removable(path, recursive)?; }
+        self.transaction(|shell| paths.iter().try_for_each(|x| shell.remove_path(x, recursive)))
+    }
+
+    // the clock used for modification times
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+    }
+
+    pub fn modified(&self, node: &FileNodePointer) -> Option<Time> {
+        self.times.get(node)
+    }
+
+    pub fn modify(&mut self, node: &FileNodePointer) {
+        self.times.record(node, (self.clock)());
+    }
+
+    // the file is created and written as a single entry of the journal
+    fn redirect(&mut self, path: &Arg, output: Option<String>, append: bool) -> Result<(), CommandError> {
+        let (parent, name) = split_path(path);
+        let directory = resolve(&self.root, &self.current, parent)?;
+        let data = output.map(|x| x + "\n").unwrap_or_default();
+        self.transaction(|shell| {
+            if find(&directory, name).is_err() { shell.touch_file(&directory, name)?; }
+            shell.write_file(&directory, name, &data, append)
+        })
+    }
+
+    pub fn check_writable(&self, node: &FileNodePointer) -> Result<(), CommandError> {
+        if self.readonly { return Err(CommandError::ReadOnly); }
+
+        let mut position = node.clone();
+        loop {
+            // a mount point stays read-only when it is seen through upper layers
+            let mut layer = position.clone();
+            loop {
+                let readonly = self.mounts.iter()
+                    .any(|x| x.readonly && NodePointer::ptr_eq(&x.point, &layer));
+                if readonly { return Err(CommandError::ReadOnly); }
+
+                let lower = if let FileType::Overlay{ name: _, lower } = &layer.borrow().0 {
+                    lower.clone()
+                } else { break };
+                layer = lower;
             }
-        } else if command == "read" {
-            let current = &self.current;
-            if let Some(arg) = iter.next() {
-                if let Ok(pointer) = find(current, arg) {
-                    let node = &pointer;
-                    if let Ok(data) = read(node) {
-                        Ok(Some(data.to_string()))
-                    } else {
-                        Err(CommandError::NotFile)
-                    }
-                } else {
-                    Err(CommandError::NotFound)
-                }
-            } else {
-                Err(CommandError::IllegalArgument)
+
+            let parent = if let Some(parent) = position.borrow().1.first() {
+                if NodePointer::ptr_eq(parent, &position) { return Ok(()); }
+                parent.clone()
+            } else { return Ok(()); };
+            position = parent;
+        }
+    }
+
+    pub fn snapshot(&mut self, name: &Arg) -> Result<(), CommandError> {
+        // the current tree is frozen as the lower layer of a new root. only
+        // the directories modified since the last snapshot are copied, and
+        // mounted trees stay layered so that read-only mounts are kept.
+        let path = pwd(&self.current);
+        let mounts = &self.mounts;
+        let snapshot = flatten(&self.root, None, &|x| mounts.iter().any(|m| NodePointer::ptr_eq(&m.point, x)));
+        self.discard_journal()?;
+        self.snapshots.retain(|x| x.name != name);
+        self.snapshots.push(Snapshot { name: name.to_string(), root: snapshot.clone() });
+        self.switch_root(snapshot, &path);
+        Ok(())
+    }
+
+    pub fn restore(&mut self, name: &Arg) -> Result<(), CommandError> {
+        if self.readonly { return Err(CommandError::ReadOnly); }
+        let snapshot = if let Some(snapshot) = self.snapshots.iter().find(|x| x.name == name) {
+            snapshot.root.clone()
+        } else {
+            return Err(CommandError::NotFound);
+        };
+        self.discard_journal()?;
+        let path = pwd(&self.current);
+        let root = self.root.clone();
+        self.switch_root(snapshot, &path);
+        if self.persistence.is_none() { return Ok(()); }
+        let records = changes(&root, &self.root);
+        self.save_all(records)
+    }
+
+    pub fn diff_snapshots(&self, from: &Arg, to: Option<&Arg>) -> Result<Vec<String>, CommandError> {
+        let lookup = |name: &Arg| self.snapshots.iter()
+            .find(|x| x.name == name)
+            .map(|x| x.root.clone())
+            .ok_or(CommandError::NotFound);
+        let from = lookup(from)?;
+        let to = if let Some(to) = to { lookup(to)? } else { self.root.clone() };
+        Ok(diff(&from, &to))
+    }
+
+    fn switch_root(&mut self, lower: FileNodePointer, path: &str) {
+        let root = FileNode::create_overlay("".to_string(), lower, vec![]).to_pointer();
+        root.borrow_mut().connect(root.clone());
+        self.current = resolve(&root, &root, path).unwrap_or_else(|_| root.clone());
+        self.root = root;
+    }
+
+    // the journal refers to nodes which are about to leave the tree, which
+    // would lose the changes of an open transaction
+    fn discard_journal(&mut self) -> Result<(), CommandError> {
+        if self.in_transaction() { return Err(CommandError::IllegalArgument); }
+        self.journal = Journal::new();
+        self.marks.clear();
+        self.history.prune();
+        self.times.prune();
+        Ok(())
+    }
+
+    pub fn undo(&mut self) -> Result<(), CommandError> {
+        if self.readonly { return Err(CommandError::ReadOnly); }
+        if !self.journal.undo() { return Err(CommandError::NotFound); }
+        let entry = self.journal.undone().cloned().unwrap_or_default();
+        self.replay(&entry, true)
+    }
+
+    pub fn redo(&mut self) -> Result<(), CommandError> {
+        if self.readonly { return Err(CommandError::ReadOnly); }
+        if !self.journal.redo() { return Err(CommandError::NotFound); }
+        let entry = self.journal.redone().cloned().unwrap_or_default();
+        self.replay(&entry, false)
+    }
+
+    // the versions, modification times and the persisted tree follow an
+    // entry undone or redone by the journal
+    fn replay(&mut self, entry: &Entry, undo: bool) -> Result<(), CommandError> {
+        let changes: Vec<&Change> = if undo { entry.iter().rev().collect() } else { entry.iter().collect() };
+        let mut records = vec![];
+        for change in changes {
+            match change {
+                Change::Edge{ directory, .. } => self.modify(directory),
+                Change::Data{ file, before, .. } => {
+                    if undo { self.history.unrecord(file); } else { self.history.record(file, before.to_string()); }
+                    self.modify(file);
+                },
+                Change::Log{ record, inverse } => {
+                    if undo { records.extend(inverse.iter().cloned()); } else { records.push(record.clone()); }
+                },
             }
-        } else if command == "write" {
-            let current = &mut self.current;
-            if let Some(arg) = iter.next() {
-                let mut index =
-                    buffer.rfind(arg).unwrap() + arg.len();
-                for i in buffer[index..].chars() {
-                    if i.is_whitespace() { index += 1; }
-                    else { break; }
+        }
+        self.save_all(records)
+    }
+
+    pub fn begin(&mut self) {
+        self.journal.begin();
+        self.marks.push(self.records.len());
+    }
+
+    pub fn commit(&mut self) -> Result<(), CommandError> {
+        if !self.journal.commit() { return Err(CommandError::IllegalArgument); }
+        self.marks.pop();
+        // the records of a transaction reach the journal as a single entry
+        if self.in_transaction() { Ok(()) } else { self.flush() }
+    }
+
+    pub fn rollback(&mut self) -> Result<(), CommandError> {
+        let entries = self.journal.uncommitted().to_vec();
+        if !self.journal.rollback() { return Err(CommandError::IllegalArgument); }
+        for change in entries.iter().rev().flat_map(|x| x.iter().rev()) {
+            if let Change::Data{ file, .. } = change { self.history.unrecord(file); }
+        }
+        if let Some(mark) = self.marks.pop() { self.records.truncate(mark); }
+        if self.in_transaction() { Ok(()) } else { self.flush() }
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.journal.in_transaction()
+    }
+
+    pub fn transaction<R, F>(&mut self, f: F) -> Result<R, CommandError>
+    where
+        F: FnOnce(&mut Shell<T>) -> Result<R, CommandError>,
+    {
+        self.begin();
+        match f(self) {
+            Ok(result) => {
+                self.commit()?;
+                Ok(result)
+            },
+            Err(e) => {
+                let _ = self.rollback();
+                Err(e)
+            },
+        }
+    }
+
+    pub fn run(&mut self, buffer: &Arg) -> CommandResult {
+        let mut results = vec![];
+        let status = self.run_each(buffer, |result| results.push(result));
+        collect(results, status)
+    }
+
+    pub fn run_each<F: FnMut(CommandResult)>(&mut self, buffer: &Arg, mut f: F) -> ExitStatus {
+        self.logger.print(&format!("run arg {}", buffer));
+        // only lines entered at the top level are expanded and remembered
+        let expanded;
+        let buffer = if self.depth == 0 {
+            self.failure = 0;
+            let expanded_line = if self.history_expansion { self.commands.expand(buffer) } else { Ok(buffer.to_string()) };
+            expanded = match expanded_line {
+                Ok(line) => line,
+                Err(e) => {
+                    self.exit_status = e.status();
+                    f(Err(e));
+                    return self.exit_status;
+                },
+            };
+            self.remember(&expanded);
+            expanded.as_str()
+        } else {
+            buffer
+        };
+        let list = match tokenize(buffer).and_then(|x| parse(&self.expand_aliases(x, &[])?)) {
+            Ok(list) => list,
+            Err(e) => {
+                self.exit_status = e.status();
+                f(Err(e));
+                return self.exit_status;
+            },
+        };
+        self.run_list(&list, &mut f);
+        self.exit_status
+    }
+
+    pub fn run_script(&mut self, script: &str) -> CommandResult {
+        // a script sourcing itself must not overflow the stack
+        if self.depth >= MAX_DEPTH { return Err(CommandError::IllegalArgument); }
+        self.depth += 1;
+        let result = self.run(script);
+        self.depth -= 1;
+        if let Some(Control::Return) = self.control { self.control = None; }
+        result
+    }
+
+    fn call(&mut self, body: &List, args: Vec<String>) -> CommandResult {
+        if self.depth >= MAX_DEPTH { return Err(CommandError::IllegalArgument); }
+        let positional = std::mem::replace(&mut self.positional, args);
+        self.depth += 1;
+        let mut results = vec![];
+        self.run_list(body, &mut |result| results.push(result));
+        self.depth -= 1;
+        self.positional = positional;
+        if let Some(Control::Return) = self.control { self.control = None; }
+        collect(results, self.exit_status)
+    }
+
+    fn run_list(&mut self, list: &List, f: &mut dyn FnMut(CommandResult)) {
+        for (index, (connector, command)) in list.iter().enumerate() {
+            if self.control.is_some() { break }
+            let execute = match connector {
+                Connector::And => self.exit_status == 0,
+                Connector::Or => self.exit_status != 0,
+                Connector::Sequence => true,
+            };
+            if !execute { continue }
+            self.run_node(command, f);
+            // the status of the last command is left to the caller
+            let tested = matches!(list.get(index + 1), None | Some((Connector::And, _)) | Some((Connector::Or, _)));
+            if !tested { self.untested(); }
+        }
+    }
+
+    fn untested(&mut self) {
+        if self.exit_status != 0 && self.control.is_none() { self.failure = self.exit_status; }
+    }
+
+    // consumes a pending break or continue, returning whether the loop ends
+    fn end_iteration(&mut self) -> bool {
+        match self.control {
+            Some(Control::Break) => {
+                self.control = None;
+                true
+            },
+            Some(Control::Continue) => {
+                self.control = None;
+                false
+            },
+            Some(Control::Return) => true,
+            None => false,
+        }
+    }
+
+    fn run_node(&mut self, command: &Command, f: &mut dyn FnMut(CommandResult)) {
+        match command {
+            Command::Simple(_) | Command::Pipeline(_) => {
+                let result = self.run_pipeline(command);
+                self.exit_status = exit_status(&result);
+                f(result);
+            },
+            Command::Group(body) => { self.run_list(body, f); },
+            Command::If{ branches, otherwise } => {
+                for (condition, body) in branches {
+                    self.run_list(condition, f);
+                    if self.control.is_some() { return }
+                    if self.exit_status == 0 { return self.run_list(body, f); }
+                }
+                match otherwise {
+                    Some(body) => self.run_list(body, f),
+                    None => { self.exit_status = 0; },
                 }
-                if index >= buffer.len() {
-                    return Err(CommandError::IllegalArgument);
+            },
+            Command::For{ name, words, body } => {
+                let values = match words {
+                    Some(words) => {
+                        let mut values = vec![];
+                        for word in words {
+                            match self.expand_glob(word) {
+                                Ok(x) => values.extend(x),
+                                Err(e) => {
+                                    self.exit_status = e.status();
+                                    return f(Err(e));
+                                },
+                            }
+                        }
+                        values
+                    },
+                    None => self.positional.clone(),
+                };
+                self.exit_status = 0;
+                self.loops += 1;
+                for value in values {
+                    self.set_variable(name, &value);
+                    self.run_list(body, f);
+                    self.untested();
+                    if self.end_iteration() { break }
                 }
-                let data = &buffer[index..];
-                if let Ok(pointer) = find(current, arg) {
-                    let node = &pointer;
-                    match write(node, data) {
-                        Ok(()) => { Ok(None) },
-                        Err(()) => {
-                            Err(CommandError::NotFile)
-                        },
+                self.loops -= 1;
+            },
+            Command::While{ condition, body, until } => {
+                let mut status = 0;
+                self.loops += 1;
+                loop {
+                    self.run_list(condition, f);
+                    if self.control.is_some() {
+                        if self.end_iteration() { break } else { continue }
                     }
-                } else {
-                    Err(CommandError::NotFound)
+                    if (self.exit_status == 0) == *until { break }
+                    self.run_list(body, f);
+                    self.untested();
+                    status = self.exit_status;
+                    if self.end_iteration() { break }
                 }
-            } else {
-                Err(CommandError::IllegalArgument)
+                self.loops -= 1;
+                if self.control.is_none() { self.exit_status = status; }
+            },
+            Command::Function{ name, body } => {
+                self.functions.insert(name.to_string(), body.clone());
+                self.exit_status = 0;
+                f(Ok(None));
+            },
+        }
+    }
+
+    fn run_pipeline(&mut self, command: &Command) -> CommandResult {
+        let stages = match command {
+            Command::Pipeline(stages) => stages.as_slice(),
+            command => std::slice::from_ref(command),
+        };
+
+        // the output of each command is the input of the next one
+        let mut data = None;
+        for (i, stage) in stages.iter().enumerate() {
+            let piped = i + 1 < stages.len();
+            data = match stage {
+                Command::Simple(tokens) => self.run_command(tokens, data, piped)?,
+                stage => {
+                    let mut results = vec![];
+                    self.run_node(stage, &mut |result| results.push(result));
+                    collect(results, self.exit_status)?
+                },
+            };
+        }
+        Ok(data)
+    }
+
+    fn expand_part(&mut self, part: &Part) -> Result<String, CommandError> {
+        match part {
+            Part::Text(text) | Part::Quoted(text) => Ok(text.to_string()),
+            Part::Variable(name) => Ok(self.variable(name).unwrap_or_default()),
+            Part::Command{ source, .. } => self.substitute(source),
+            Part::Arithmetic(expression) => Ok(evaluate(expression, |name| self.variable(name))?.to_string()),
+        }
+    }
+
+    fn expand(&mut self, word: &Word) -> Result<String, CommandError> {
+        word.iter().map(|part| self.expand_part(part)).collect()
+    }
+
+    fn substitute(&mut self, source: &str) -> Result<String, CommandError> {
+        // runs like a subshell, the directory and variables are restored
+        let current = self.current.clone();
+        let environment = self.environment.clone();
+        let positional = self.positional.clone();
+        let functions = self.functions.clone();
+        let captured = std::mem::replace(&mut self.captured, true);
+        let result = self.run_script(source);
+        self.current = current;
+        self.environment = environment;
+        self.positional = positional;
+        self.functions = functions;
+        self.captured = captured;
+
+        // a failure sets `$?` and keeps the output before it, the command
+        // around the substitution still runs
+        let status = exit_status(&result);
+        self.exit_status = status;
+        self.substitution = Some(status);
+        let output = match result {
+            Ok(output) => output.unwrap_or_default(),
+            Err(CommandError::Partial(output, _)) => output,
+            Err(e) => {
+                self.logger.print(&format!("substitution {}", e));
+                String::new()
+            },
+        };
+        Ok(output.trim_end_matches('\n').to_string())
+    }
+
+    fn expand_glob(&mut self, word: &Word) -> Result<Vec<String>, CommandError> {
+        // "$@" is one word per positional parameter
+        let mut parts = word.iter().filter(|x| !matches!(x, Part::Quoted(text) if text.is_empty()));
+        if let (Some(Part::Variable(name)), None) = (parts.next(), parts.next()) {
+            if name == "@" { return Ok(self.positional.clone()); }
+        }
+
+        // each field keeps its value and a pattern in which only unquoted
+        // text keeps its special meaning
+        let mut fields: Vec<(String, String)> = vec![];
+        let mut field: Option<(String, String)> = None;
+        for part in word {
+            match part {
+                Part::Text(text) => {
+                    let field = field.get_or_insert_with(Default::default);
+                    field.0 += text;
+                    field.1 += text;
+                },
+                Part::Command{ source, quoted: false } => {
+                    // unquoted output is split into words at whitespace
+                    let value = self.substitute(source)?;
+                    if value.starts_with(char::is_whitespace) { fields.extend(field.take()); }
+                    for (i, piece) in value.split_whitespace().enumerate() {
+                        if i > 0 { fields.extend(field.take()); }
+                        let field = field.get_or_insert_with(Default::default);
+                        field.0 += piece;
+                        field.1 += &escape(piece);
+                    }
+                    if value.ends_with(char::is_whitespace) { fields.extend(field.take()); }
+                },
+                part => {
+                    let value = self.expand_part(part)?;
+                    let field = field.get_or_insert_with(Default::default);
+                    field.0 += &value;
+                    field.1 += &escape(&value);
+                },
             }
-        } else {
-            Err(CommandError::CommandNotFound(command.to_string()))
         }
+        fields.extend(field);
+
+        let mut result = vec![];
+        for (value, pattern) in fields {
+            let paths = if is_pattern(&pattern) { glob_paths(&self.root, &self.current, &pattern) } else { vec![] };
+            if paths.is_empty() { result.push(value); }
+            else { result.extend(paths.into_iter().map(|x| x.0)); }
+        }
+        Ok(result)
     }
-}
 
+    fn run_command(&mut self, tokens: &[Token], input: Option<String>, piped: bool) -> CommandResult {
+        // a command made only of `name=value` words sets variables
+        let assignments = tokens.iter()
+            .map(|x| match x {
+                Token::Word(word) => assignment(word).map(|name| (name, word)),
+                Token::Operator(_) => None,
+            })
+            .collect::<Option<Vec<(&str, &Word)>>>();
+        if let Some(assignments) = assignments {
+            self.substitution = None;
+            for (name, word) in assignments {
+                let value = self.expand(word)?[name.len() + 1..].to_string();
+                self.set_variable(name, &value);
+            }
+            // the status of the assignments is the one of their last substitution
+            return match self.substitution.take() {
+                Some(status) if status != 0 => Err(CommandError::Failure(status)),
+                _ => Ok(None),
+            };
+        }
+
+        let mut words = vec![];
+        let mut source = None;
+        let mut output = None;
+
+        let mut tokens = tokens.iter();
+        while let Some(token) = tokens.next() {
+            match token {
+                Token::Word(word) => { words.extend(self.expand_glob(word)?); },
+                Token::Operator(operator) => {
+                    let target = if let Some(Token::Word(target)) = tokens.next() { self.expand(target)? }
+                        else { return Err(CommandError::IllegalArgument); };
+                    match operator.as_str() {
+                        "<" => { source = Some(target); },
+                        ">" => { output = Some((target, false)); },
+                        ">>" => { output = Some((target, true)); },
+                        _ => { return Err(CommandError::IllegalArgument); },
+                    }
+                },
+            }
+        }
+
+        let input = match source {
+            Some(path) => Some(self.read_path(&path)?),
+            None => input,
+        };
+        let argv: Vec<&Arg> = words.iter().map(|x| x.as_str()).collect();
+        let redirected = piped || output.is_some() || self.captured;
+        let result = self.invoke(&argv, input, redirected)?;
+
+        match output {
+            Some((path, append)) => {
+                self.redirect(&path, result, append)?;
+                Ok(None)
+            },
+            None => Ok(result),
+        }
+    }
+
+    pub(crate) fn input(&self, arg: Option<&&Arg>, input: Option<String>) -> Result<String, CommandError> {
+        match (arg, input) {
+            (Some(arg), _) => self.read_path(arg),
+            (None, Some(input)) => Ok(input),
+            (None, None) => Err(CommandError::IllegalArgument),
+        }
+    }
+
+    pub fn execute(&mut self, argv: &[&Arg], input: Option<String>) -> CommandResult {
+        self.invoke(argv, input, false)
+    }
+
+    fn invoke(&mut self, argv: &[&Arg], input: Option<String>, redirected: bool) -> CommandResult {
+        let command = match argv.first() {
+            Some(head) if !head.is_empty() => *head,
+            _ => return Ok(None),
+        };
+
+        // functions take precedence over commands
+        if let Some(body) = self.functions.get(command).cloned() {
+            let args = argv[1..].iter().map(|x| x.to_string()).collect();
+            return self.call(&body, args);
+        }
+
+        let definition = self.registry.get(command).ok_or_else(|| CommandError::CommandNotFound(command.to_string()))?;
+        if argv[1..] == ["--help"] && definition.takes_help() { return Ok(Some(definition.help())); }
+        let mut args = definition.parse(&argv[1..])?;
+        args.redirected = redirected;
+        definition.execute(self, &args, input)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use crate::virtual_filesystem_core::graph::Graph;
+    use crate::virtual_filesystem_core::filesystem::{FileNode, FileType};
+    use crate::virtual_filesystem::command::touch;
+    use crate::virtual_filesystem_core::logger::LoggerRepository;
+    use crate::virtual_filesystem::registry::{Arguments, CommandOption, ShellCommand};
+    use crate::virtual_filesystem::shell::{CommandError, CommandResult, Shell, RC_FILE};
+
+    #[test]
+    fn test_enter() {
+        let shell = &mut Shell::init();
+
+        let buffer = "";
+        assert_eq!(shell.run(buffer), Ok(None));
+    }
+
+    #[test]
+    fn test_exit() {
+        let shell = &mut Shell::init();
+
+        let buffer = "exit";
+        assert_eq!(shell.run(buffer), Err(CommandError::CommandNotFound(buffer.to_string())));
+    }
+
+    #[test]
+    fn test_help() {
+        let shell = &mut Shell::init();
+
+        let buffer = ":?";
+        assert_eq!(shell.run(buffer), Err(CommandError::CommandNotFound(buffer.to_string())));
+
+        let help = shell.run("help").unwrap().unwrap();
+        assert!(help.starts_with("command list\n  [ [expression] ]\n  alias"));
+        assert!(help.contains("\n  head [-n lines] [file]\n"));
+        assert!(help.contains("\nredirection\n"));
+
+        let buffer = "help sort";
+        assert_eq!(shell.run(buffer), Ok(Some("usage: sort [-r] [file]\nsort the lines\n  -r  sort in reverse order".to_string())));
+        let buffer = "snapshot --help";
+        assert_eq!(shell.run(buffer), Ok(Some("usage: snapshot [-l] [-d from] [name] | [to]\ntake a snapshot, list the snapshots or show the changes between them\n  -l       list the snapshots\n  -d from  show the changes from a snapshot".to_string())));
+        let buffer = "help none";
+        assert_eq!(shell.run(buffer), Err(CommandError::CommandNotFound("none".to_string())));
+
+        // the arguments of commands are not help requests
+        let buffer = "echo a --help";
+        assert_eq!(shell.run(buffer), Ok(Some("a --help".to_string())));
+        let buffer = "echo --help";
+        assert_eq!(shell.run(buffer), Ok(Some("--help".to_string())));
+        let buffer = "touch f; write f --help; read f";
+        assert_eq!(shell.run(buffer), Ok(Some("--help".to_string())));
+        let buffer = "test --help";
+        assert_eq!(shell.run(buffer), Ok(None));
+        assert_eq!(shell.complete("help gr", 7), (5, vec!["grep".to_string()]));
+    }
+
+    thread_local!(static NOW: std::cell::Cell<u64> = const { std::cell::Cell::new(0) });
+
+    fn now() -> u64 {
+        NOW.with(|x| x.get())
+    }
+
+    #[test]
+    fn test_ls() {
+        let shell = &mut Shell::init();
+        shell.set_clock(now);
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("".to_string())));
+
+        let buffer = "ls a";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+
+        NOW.with(|x| x.set(1_700_000_000));
+        let buffer = "touch a; write a hi; touch .hidden";
+        assert_eq!(shell.run(buffer), Ok(None));
+        NOW.with(|x| x.set(1_700_000_060));
+        let buffer = "mkdir dir; cd dir; touch c; cd ..";
+        assert_eq!(shell.run(buffer), Ok(None));
+        NOW.with(|x| x.set(1_700_000_120));
+        let buffer = "touch b; write b hello";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("a\tb\tdir".to_string())));
+
+        let buffer = "ls -a";
+        assert_eq!(shell.run(buffer), Ok(Some(".hidden\ta\tb\tdir".to_string())));
+
+        let buffer = "ls -1r";
+        assert_eq!(shell.run(buffer), Ok(Some("dir\nb\na".to_string())));
+
+        let buffer = "ls -S";
+        assert_eq!(shell.run(buffer), Ok(Some("b\ta\tdir".to_string())));
+
+        let buffer = "ls -t";
+        assert_eq!(shell.run(buffer), Ok(Some("b\tdir\ta".to_string())));
+
+        let buffer = "ls -l";
+        let expected = "\
+-rw-r--r-- 2 2023-11-14 22:13 a
+-rw-r--r-- 5 2023-11-14 22:15 b
+drwxr-xr-x 1 2023-11-14 22:14 dir";
+        assert_eq!(shell.run(buffer), Ok(Some(expected.to_string())));
+
+        let buffer = "ls dir a";
+        assert_eq!(shell.run(buffer), Ok(Some("a\n\ndir:\nc".to_string())));
+
+        let buffer = "ls -R";
+        assert_eq!(shell.run(buffer), Ok(Some(".:\na\tb\tdir\n\n./dir:\nc".to_string())));
+
+        let buffer = "export COLUMNS=16; ls";
+        assert_eq!(shell.run(buffer), Ok(Some("a\tdir\nb".to_string())));
+
+        let buffer = "ls | head -n 2";
+        assert_eq!(shell.run(buffer), Ok(Some("a\nb".to_string())));
+
+        let buffer = "ls -x";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        // undoing a change modifies the file again
+        NOW.with(|x| x.set(1_700_000_180));
+        let buffer = "write a x; undo; ls -1t";
+        assert_eq!(shell.run(buffer), Ok(Some("a\nb\ndir".to_string())));
+    }
+
+    #[test]
+    fn test_tree() {
+        let shell = &mut Shell::init();
+
+        let buffer = "tree";
+        assert_eq!(shell.run(buffer), Ok(Some(".\n\n0 directories, 0 files".to_string())));
+
+        let buffer = "mkdir src; mkdir docs; touch readme; touch .hidden; cd src; mkdir bin; touch main; cd bin; touch cli; cd /";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "tree";
+        let expected = "\
+.
+├── docs
+├── readme
+└── src
+    ├── bin
+    │   └── cli
+    └── main
+
+3 directories, 3 files";
+        assert_eq!(shell.run(buffer), Ok(Some(expected.to_string())));
+
+        let buffer = "tree -L 1 src";
+        assert_eq!(shell.run(buffer), Ok(Some("src\n├── bin\n└── main\n\n1 directory, 1 file".to_string())));
+
+        let buffer = "tree /src/bin -L 1";
+        assert_eq!(shell.run(buffer), Ok(Some("/src/bin\n└── cli\n\n0 directories, 1 file".to_string())));
+
+        let buffer = "tree readme";
+        assert_eq!(shell.run(buffer), Ok(Some("readme\n\n0 directories, 1 file".to_string())));
+
+        let buffer = "tree -L 0";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "tree src docs";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "tree none";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+    }
+
+    #[test]
+    fn test_pwd() {
+        let shell = &mut Shell::init();
+
+        let buffer = "pwd";
+        assert_eq!(shell.run(buffer), Ok(Some("/".to_string())));
+
+        let buffer = "pwd a";
+        assert_eq!(shell.run(buffer), Ok(Some("/".to_string())));
+
+        let buffer = "mkdir a";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "cd a";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "pwd";
+        assert_eq!(shell.run(buffer), Ok(Some("/a".to_string())));
+    }
+
+    #[test]
+    fn test_cd() {
+        let shell = &mut Shell::init();
+
+        let buffer = "cd";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "cd a";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+
+        let buffer = "cd b c";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+
+        let buffer = "pwd";
+        assert_eq!(shell.run(buffer), Ok(Some("/".to_string())));
+
+        let buffer = "cd .";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "pwd";
+        assert_eq!(shell.run(buffer), Ok(Some("/".to_string())));
+
+        let buffer = "cd ..";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "pwd";
+        assert_eq!(shell.run(buffer), Ok(Some("/".to_string())));
+
+        let buffer = "mkdir a";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "cd a";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "cd .";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "pwd";
+        assert_eq!(shell.run(buffer), Ok(Some("/a".to_string())));
+
+        let buffer = "mkdir b";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "cd b c";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "cd .";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "pwd";
+        assert_eq!(shell.run(buffer), Ok(Some("/a/b".to_string())));
+
+        let buffer = "cd ..";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "pwd";
+        assert_eq!(shell.run(buffer), Ok(Some("/a".to_string())));
+
+        let buffer = "cd /";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "pwd";
+        assert_eq!(shell.run(buffer), Ok(Some("/".to_string())));
+
+        let buffer = "cd a/b";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "cd -";
+        assert_eq!(shell.run(buffer), Ok(Some("/".to_string())));
+
+        let buffer = "cd -";
+        assert_eq!(shell.run(buffer), Ok(Some("/a/b".to_string())));
+
+        let buffer = "cd /a/../a/./b/..";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "pwd";
+        assert_eq!(shell.run(buffer), Ok(Some("/a".to_string())));
+
+        let buffer = "export HOME=/a/b && cd && pwd";
+        assert_eq!(shell.run(buffer), Ok(Some("/a/b".to_string())));
+
+        let buffer = "unset HOME OLDPWD";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "cd";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "cd -";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+    }
+
+    #[test]
+    fn test_environment() {
+        let shell = &mut Shell::init();
+
+        let buffer = "env";
+        assert_eq!(shell.run(buffer), Ok(Some("HOME=/\nPWD=/\nUSER=user".to_string())));
+
+        let buffer = "echo $HOME ${USER} $PWD $MISSING.";
+        assert_eq!(shell.run(buffer), Ok(Some("/ user / .".to_string())));
+
+        let buffer = "export NAME='my file' EMPTY";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "touch \"$NAME\" && ls";
+        assert_eq!(shell.run(buffer), Ok(Some("my file".to_string())));
+
+        let buffer = "echo '$NAME' \"${NAME}s\" \\$NAME";
+        assert_eq!(shell.run(buffer), Ok(Some("$NAME my files $NAME".to_string())));
+
+        let buffer = "mkdir dir; cd dir; set";
+        assert_eq!(shell.run(buffer), Ok(Some("EMPTY=\nHOME=/\nNAME=my file\nOLDPWD=/\nPWD=/dir\nUSER=user".to_string())));
+
+        let buffer = "unset NAME EMPTY && export";
+        assert_eq!(shell.run(buffer), Ok(Some("HOME=/\nOLDPWD=/\nPWD=/dir\nUSER=user".to_string())));
+
+        let buffer = "export 1A=x";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "unset";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        assert_eq!(shell.variable("PWD"), Some("/dir".to_string()));
+        shell.set_variable("X", "1");
+        assert_eq!(shell.run("echo $X"), Ok(Some("1".to_string())));
+    }
+
+    #[test]
+    fn test_glob() {
+        let shell = &mut Shell::init();
+
+        let buffer = "touch a.txt; touch b.txt; write a.txt A; write b.txt B; mkdir logs";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "ls *.txt";
+        assert_eq!(shell.run(buffer), Ok(Some("a.txt\tb.txt".to_string())));
+
+        let buffer = "echo *.none '*.txt' \\*.txt \"*\".txt [ab].t?t";
+        assert_eq!(shell.run(buffer), Ok(Some("*.none *.txt *.txt *.txt a.txt b.txt".to_string())));
+
+        let buffer = "cd logs && touch 1.log && touch 2.log && mkdir old && cd old && touch 0.log && cd /";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "echo **/*.log";
+        assert_eq!(shell.run(buffer), Ok(Some("logs/1.log logs/2.log logs/old/0.log".to_string())));
+
+        let buffer = "export P='*.txt'; echo $P";
+        assert_eq!(shell.run(buffer), Ok(Some("*.txt".to_string())));
+
+        let buffer = "rm logs/*.log && cd logs && ls";
+        assert_eq!(shell.run(buffer), Ok(Some("old".to_string())));
+
+        let buffer = "cd old && rm ../../*.txt && cd / && ls";
+        assert_eq!(shell.run(buffer), Ok(Some("logs".to_string())));
+    }
+
+    #[test]
+    fn test_script() {
+        let shell = &mut Shell::init();
+
+        let buffer = "touch setup; write setup '# create the output directory\nmkdir out && cd out\n\ntouch log # empty\npwd'";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "source setup";
+        assert_eq!(shell.run(buffer), Ok(Some("/out".to_string())));
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("log".to_string())));
+
+        let buffer = "sh /setup";
+        assert_eq!(shell.run(buffer), Ok(Some("/out/out".to_string())));
+
+        let buffer = "cd /; touch failing; write failing 'cd missing\necho next\ncd missing'";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "sh failing";
+        assert_eq!(shell.run(buffer), Err(CommandError::Partial("not found.\nnext".to_string(), Box::new(CommandError::NotFound))));
+        assert_eq!(shell.exit_status, 1);
+
+        let buffer = "touch loop; write loop 'sh loop'; sh loop";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "sh";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "sh missing";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+
+        assert_eq!(shell.run_script("echo a\n\n  # comment\necho b"), Ok(Some("a\nb".to_string())));
+        assert_eq!(shell.run("echo 'unterminated"), Err(CommandError::IllegalArgument));
+        assert_eq!(shell.exit_status, 1);
+
+        let mut results = vec![];
+        let status = shell.run_each("echo a\ncd missing\nunknown", |x| results.push(x));
+        assert_eq!(status, 127);
+        assert_eq!(results, vec![
+            Ok(Some("a".to_string())),
+            Err(CommandError::NotFound),
+            Err(CommandError::CommandNotFound("unknown".to_string())),
+        ]);
+        assert_eq!(shell.failure, 1);
+
+        // failures which are not tested are remembered for the exit status
+        assert_eq!(shell.run_each("false; true", |_| {}), 0);
+        assert_eq!(shell.failure, 1);
+        assert_eq!(shell.run_each("[ -d out ] || mkdir out; cd out && pwd", |_| {}), 0);
+        assert_eq!(shell.failure, 0);
+        assert_eq!(shell.run_each("if false; then true; fi\nwhile false; do true; done\ntrue", |_| {}), 0);
+        assert_eq!(shell.failure, 0);
+        assert_eq!(shell.run_each("for x in a b; do [ $x = b ]; done; f() { false; true; }; f", |_| {}), 0);
+        assert_eq!(shell.failure, 1);
+    }
+
+    #[test]
+    fn test_if() {
+        let shell = &mut Shell::init();
+
+        let buffer = "if cd missing; then echo yes; else echo no; fi";
+        assert_eq!(shell.run(buffer), Ok(Some("not found.\nno".to_string())));
+
+        let buffer = "mkdir dir; if cd dir\nthen\n  pwd\nfi";
+        assert_eq!(shell.run(buffer), Ok(Some("/dir".to_string())));
+
+        let buffer = "if false; then echo a; elif true; then echo b; else echo c; fi";
+        assert_eq!(shell.run(buffer), Ok(Some("b".to_string())));
+
+        let buffer = "if false; then echo a; fi";
+        assert_eq!(shell.run(buffer), Ok(None));
+        assert_eq!(shell.exit_status, 0);
+
+        let buffer = "false";
+        assert_eq!(shell.run(buffer), Err(CommandError::Failure(1)));
+
+        let buffer = "true && { echo a; echo b; } | wc";
+        assert_eq!(shell.run(buffer), Ok(Some("2 2 3".to_string())));
+
+        let buffer = "if true; then echo a";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+    }
+
+    #[test]
+    fn test_test() {
+        let shell = &mut Shell::init();
+
+        let buffer = "[ -d build ] || mkdir build; [ -d build ] && echo exists";
+        assert_eq!(shell.run(buffer), Ok(Some("exists".to_string())));
+
+        let buffer = "touch empty; touch file; write file data";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "test -e build && test -e file && test -e missing";
+        assert_eq!(shell.run(buffer), Err(CommandError::Failure(1)));
+
+        let buffer = "test -f file && test ! -f build && test -d build && test ! -d file && echo ok";
+        assert_eq!(shell.run(buffer), Ok(Some("ok".to_string())));
+
+        let buffer = "test -s file && test ! -s empty && test -r file && test -w file && echo ok";
+        assert_eq!(shell.run(buffer), Ok(Some("ok".to_string())));
+
+        let buffer = "test ! -s build && test ! -r missing && cd build && touch a && cd .. && test -s build && echo ok";
+        assert_eq!(shell.run(buffer), Ok(Some("ok".to_string())));
+
+        let buffer = "x=abc; [ $x = abc ] && [ $x != abd ] && [ -n $x ] && [ -z '' ] && [ $x ] && [ ! '' ] && echo ok";
+        assert_eq!(shell.run(buffer), Ok(Some("ok".to_string())));
+
+        let buffer = "[ 2 -lt 10 ] && [ 10 -gt 2 ] && [ -1 -le -1 ] && [ 3 -ge 3 ] && [ 3 -eq 3 ] && [ 3 -ne 4 ] && echo ok";
+        assert_eq!(shell.run(buffer), Ok(Some("ok".to_string())));
+
+        let buffer = "[ a = b -o b = b ] && [ ! a = b -a b = b ] && echo ok";
+        assert_eq!(shell.run(buffer), Ok(Some("ok".to_string())));
+
+        let buffer = "[ a = b ]; echo $?";
+        assert_eq!(shell.run(buffer), Ok(Some("1".to_string())));
+
+        let buffer = "[ ]";
+        assert_eq!(shell.run(buffer), Err(CommandError::Failure(1)));
+
+        let buffer = "[ a = a";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "test x -lt 1";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "test a b c";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let tree = Shell::init();
+        shell.mount("ro", tree.root.clone(), true).unwrap();
+        let buffer = "test -d ro && test ! -w ro && echo readonly";
+        assert_eq!(shell.run(buffer), Ok(Some("readonly".to_string())));
+
+        let buffer = "i=; while [ x$i != xxxx ]; do i=x$i; done; echo $i";
+        assert_eq!(shell.run(buffer), Ok(Some("xxx".to_string())));
+    }
+
+    #[test]
+    fn test_substitution() {
+        let shell = &mut Shell::init();
+
+        let buffer = "mkdir build; touch target_dir; write target_dir build; cd $(read target_dir) && pwd";
+        assert_eq!(shell.run(buffer), Ok(Some("/build".to_string())));
+
+        let buffer = "cd /; echo \"[$(pwd)]\" $(echo $(echo nested))";
+        assert_eq!(shell.run(buffer), Ok(Some("[/] nested".to_string())));
+
+        let buffer = "touch 'a b'; touch c; for x in $(ls); do echo \"<$x>\"; done";
+        assert_eq!(shell.run(buffer), Ok(Some("<a>\n<b>\n<build>\n<c>\n<target_dir>".to_string())));
+
+        let buffer = "for x in \"$(ls | grep ' ')\" \"$(ls | head -n 2)\"; do echo \"<$x>\"; done";
+        assert_eq!(shell.run(buffer), Ok(Some("<a b>\n<a b\nbuild>".to_string())));
+
+        let buffer = "x=outer; echo $(x=inner; cd build; echo $x $PWD) $x $PWD";
+        assert_eq!(shell.run(buffer), Ok(Some("inner /build outer /".to_string())));
+
+        let buffer = "echo a$(true)b $(true) '$(pwd)'";
+        assert_eq!(shell.run(buffer), Ok(Some("ab $(pwd)".to_string())));
+
+        // a failing substitution is empty and the command still runs
+        let buffer = "echo [$(read missing)]";
+        assert_eq!(shell.run(buffer), Ok(Some("[]".to_string())));
+
+        let buffer = "echo $(read missing) $?; echo \"$(echo kept; false)\" $?";
+        assert_eq!(shell.run(buffer), Ok(Some("1\nkept 1".to_string())));
+
+        let buffer = "x=$(read missing) || echo failed $?; x=$(true) && echo ok";
+        assert_eq!(shell.run(buffer), Ok(Some("failed 1\nok".to_string())));
+
+        // the depth is limited, the innermost substitution fails
+        let buffer = "f() { echo x$(f); }; f | wc";
+        assert_eq!(shell.run(buffer), Ok(Some("1 1 32".to_string())));
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let shell = &mut Shell::init();
+
+        let buffer = "echo $((1 + 2 * 3)) $(( (1 + 2) * 3 ))";
+        assert_eq!(shell.run(buffer), Ok(Some("7 9".to_string())));
+
+        let buffer = "i=0; total=0; while [ $i -lt 5 ]; do i=$((i + 1)); total=$(($total + i)); done; echo $total";
+        assert_eq!(shell.run(buffer), Ok(Some("15".to_string())));
+
+        let buffer = "n=$(echo 6); echo \"$((n * 7))\" $((n > 5)) $((-n % 4))";
+        assert_eq!(shell.run(buffer), Ok(Some("42 1 -2".to_string())));
+
+        let buffer = "echo $((1 / 0))";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "x=abc; echo $((x + 1))";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "echo $((0 && 1 / 0)) $((1 || x))";
+        assert_eq!(shell.run(buffer), Ok(Some("0 1".to_string())));
+
+        let buffer = format!("echo $(({}1{}))", "(".repeat(2000), ")".repeat(2000));
+        assert_eq!(shell.run(&buffer), Err(CommandError::IllegalArgument));
+    }
+
+    #[test]
+    fn test_alias() {
+        let shell = &mut Shell::init();
+
+        let buffer = "alias ll='ls' say='echo hello'; alias";
+        assert_eq!(shell.run(buffer), Ok(Some("alias ll='ls'\nalias say='echo hello'".to_string())));
+
+        let buffer = "touch a; ll; say world; alias say";
+        assert_eq!(shell.run(buffer), Ok(Some("a\nhello world\nalias say='echo hello'".to_string())));
+
+        // only words in command position are replaced
+        let buffer = "echo say; 'say' x; if true; then say; fi";
+        assert_eq!(shell.run(buffer), Ok(Some("say\nsay command not found.\nhello".to_string())));
+        let buffer = "echo say | say; if true; then say; fi";
+        assert_eq!(shell.run(buffer), Ok(Some("hello\nhello".to_string())));
+
+        // an alias referring to itself is not expanded again
+        // aliases take effect from the next input
+        let buffer = "alias echo='echo [' loop='loop'; echo ]";
+        assert_eq!(shell.run(buffer), Ok(Some("]".to_string())));
+        let buffer = "echo ]";
+        assert_eq!(shell.run(buffer), Ok(Some("[ ]".to_string())));
+        let buffer = "loop";
+        assert_eq!(shell.run(buffer), Err(CommandError::CommandNotFound("loop".to_string())));
+
+        let buffer = "unalias echo loop; echo ]; alias none";
+        assert_eq!(shell.run(buffer), Err(CommandError::Partial("[ ]".to_string(), Box::new(CommandError::NotFound))));
+        let buffer = "unalias -a; alias";
+        assert_eq!(shell.run(buffer), Ok(Some("".to_string())));
+        let buffer = "unalias ll";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+    }
+
+    #[test]
+    fn test_history() {
+        let shell = &mut Shell::init();
+
+        let buffer = "mkdir dir";
+        assert_eq!(shell.run(buffer), Ok(None));
+        let buffer = "echo one";
+        assert_eq!(shell.run(buffer), Ok(Some("one".to_string())));
+        let buffer = "!! two";
+        assert_eq!(shell.run(buffer), Ok(Some("one two".to_string())));
+        let buffer = "!ec; history 2";
+        assert_eq!(shell.run(buffer), Ok(Some("one two\n    3  echo one two\n    4  echo one two; history 2".to_string())));
+        let buffer = "!99";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+
+        // scripts and substitutions are not remembered
+        let buffer = "touch script; write script 'echo $(pwd)'; sh script";
+        assert_eq!(shell.run(buffer), Ok(Some("/".to_string())));
+        assert_eq!(shell.command_history().last(), Some(&(5, buffer)));
+
+        let buffer = "history -c; history";
+        assert_eq!(shell.run(buffer), Ok(Some("".to_string())));
+
+        // lines are kept in $HISTFILE when it is set, outside of the undo history
+        let buffer = "export HISTFILE=/.history";
+        assert_eq!(shell.run(buffer), Ok(None));
+        let buffer = "cd dir; read /.history";
+        assert_eq!(shell.run(buffer), Ok(Some("export HISTFILE=/.history\ncd dir; read /.history\n".to_string())));
+        let buffer = "undo; cd /; read script; ls";
+        assert_eq!(shell.run(buffer), Ok(Some("\ndir\tscript".to_string())));
+
+        // each line is appended to the file
+        let buffer = "echo kept >> /.history";
+        assert_eq!(shell.run(buffer), Ok(None));
+        let buffer = "read /.history";
+        assert_eq!(shell.run(buffer), Ok(Some("export HISTFILE=/.history\ncd dir; read /.history\nundo; cd /; read script; ls\n\
+            echo kept >> /.history\nkept\nread /.history\n".to_string())));
+
+        let buffer = "touch .vfsrc; write .vfsrc 'export HISTFILE=/.history'";
+        assert_eq!(shell.run(buffer), Ok(None));
+        let overlay = &mut Shell::init_overlay(shell.root.clone());
+        assert_eq!(overlay.command_history().len(), 7);
+        assert_eq!(overlay.run("!?HIST? && history 1"), Ok(Some("    8  touch .vfsrc; write .vfsrc 'export HISTFILE=/.history' && history 1".to_string())));
+
+        // scripts run without history expansion
+        overlay.set_history_expansion(false);
+        assert_eq!(overlay.run("echo !! \"!?HIST?\""), Ok(Some("!! !?HIST?".to_string())));
+    }
+
+    #[test]
+    fn test_complete() {
+        let shell = &mut Shell::init();
+        let buffer = "mkdir src; touch setup; cd src; touch shell.rs; cd /; alias sl=ls; say() { echo $1; }";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let strings = |x: &[&str]| x.iter().map(|x| x.to_string()).collect::<Vec<String>>();
+        assert_eq!(shell.complete("s", 1), (0, strings(&["say", "set", "sh", "shift", "sl", "snapshot", "sort", "source", "status"])));
+        assert_eq!(shell.complete("ls | wc; hi", 11), (9, strings(&["history"])));
+        assert_eq!(shell.complete("read s", 6), (5, strings(&["setup", "src/"])));
+        assert_eq!(shell.complete("read src/ ", 9), (5, strings(&["src/shell.rs"])));
+        assert_eq!(shell.complete("cd s && ls", 4), (3, strings(&["setup", "src/"])));
+        assert_eq!(shell.complete("echo $HO", 8), (5, strings(&["$HOME"])));
+        let names = shell.complete("", 0).1;
+        assert!(["[", "cd", "echo", "history", "say", "sl"].iter().all(|x| names.contains(&x.to_string())));
+        assert!(!names.contains(&"src/".to_string()));
+
+        assert_eq!(shell.complete("ls | grep -", 11), (10, strings(&["-v"])));
+        assert_eq!(shell.complete("if [ -e", 7), (5, strings(&["-e", "-eq"])));
+        assert_eq!(shell.complete("snapshot - x", 10), (9, strings(&["-d", "-l"])));
+        assert_eq!(shell.complete("ls -", 4), (3, strings(&["-1", "-R", "-S", "-a", "-l", "-r", "-t"])));
+    }
+
+    #[test]
+    fn test_register() {
+        const OPTIONS: [CommandOption; 1] = [CommandOption::flag("-t", "count the total length")];
+        struct Count;
+        impl<T: LoggerRepository> ShellCommand<T> for Count {
+            fn name(&self) -> &str { "count" }
+            fn description(&self) -> &str { "count the arguments" }
+            fn operands(&self) -> &str { "[args...]" }
+            fn options(&self) -> &[CommandOption] { &OPTIONS }
+            fn execute(&self, shell: &mut Shell<T>, args: &Arguments, _: Option<String>) -> CommandResult {
+                shell.set_variable("COUNTED", "1");
+                let count = if args.has("-t") { args.operands.iter().map(|x| x.len()).sum() } else { args.operands.len() };
+                Ok(Some(count.to_string()))
+            }
+        }
+
+        // a command changing files through the journaled operations can be undone
+        struct Stamp;
+        impl<T: LoggerRepository> ShellCommand<T> for Stamp {
+            fn name(&self) -> &str { "stamp" }
+            fn description(&self) -> &str { "create a file holding its name" }
+            fn operands(&self) -> &str { "name" }
+            fn execute(&self, shell: &mut Shell<T>, args: &Arguments, _: Option<String>) -> CommandResult {
+                let name = args.operand(0).ok_or(CommandError::IllegalArgument)?;
+                let directory = shell.current.clone();
+                shell.transaction(|shell| {
+                    shell.touch_file(&directory, name)?;
+                    shell.write_file(&directory, name, name, false)
+                })?;
+                Ok(None)
+            }
+        }
+
+        let shell = &mut Shell::init();
+        shell.register(Count);
+        shell.register(Stamp);
+        assert_eq!(shell.command("count").map(|x| x.usage()), Some("count [-t] [args...]".to_string()));
+        assert_eq!(shell.run("stamp a; read a"), Ok(Some("a".to_string())));
+        assert_eq!(shell.read_path("a"), Ok("a".to_string()));
+        assert_eq!(shell.run("undo; ls"), Ok(Some("".to_string())));
+        assert_eq!(shell.run("redo; ls"), Ok(Some("a".to_string())));
+        assert_eq!(shell.remove_path("a", false), Ok(()));
+        assert_eq!(shell.run("ls"), Ok(Some("".to_string())));
+        assert_eq!(shell.run("undo; ls"), Ok(Some("a".to_string())));
+        assert_eq!(shell.run("rm a"), Ok(None));
+        let root = shell.root.clone();
+        assert_eq!(shell.make_directory(&root, "made"), Ok(()));
+        assert_eq!(shell.run("ls"), Ok(Some("made".to_string())));
+        assert_eq!(shell.run("undo; ls"), Ok(Some("".to_string())));
+
+        let buffer = "count a b c; count -t ab cd; count -x";
+        assert_eq!(shell.run(buffer), Err(CommandError::Partial("3\n4".to_string(), Box::new(CommandError::IllegalArgument))));
+        let buffer = "count a b c; count -t ab cd; echo $COUNTED";
+        assert_eq!(shell.run(buffer), Ok(Some("3\n4\n1".to_string())));
+        assert_eq!(shell.complete("cou", 3).1, vec!["count"]);
+        assert_eq!(shell.complete("count -", 7).1, vec!["-t"]);
+
+        // a function still takes precedence, and built-in commands can be replaced or removed
+        let buffer = "count() { echo function; }; count";
+        assert_eq!(shell.run(buffer), Ok(Some("function".to_string())));
+        assert_eq!(shell.unregister("echo"), Ok(()));
+        assert_eq!(shell.unregister("echo"), Err(CommandError::NotFound));
+        let buffer = "echo a";
+        assert_eq!(shell.run(buffer), Err(CommandError::CommandNotFound("echo".to_string())));
+    }
+
+    #[test]
+    fn test_rc() {
+        let shell = &mut Shell::init();
+        let buffer = "touch .vfsrc; write .vfsrc 'alias hi=\"echo hi\"\nexport GREETING=hello\nmkdir work'";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        // the rc file is read from $HOME when a shell is initialised
+        let overlay = &mut Shell::init_overlay(shell.root.clone());
+        assert_eq!(overlay.run("hi; echo $GREETING; ls"), Ok(Some("hi\nhello\nwork".to_string())));
+
+        // startup commands can not be undone
+        assert_eq!(overlay.run("undo"), Err(CommandError::NotFound));
+        assert_eq!(overlay.rc, Ok(None));
+
+        // the output and failures are kept for the frontend
+        let buffer = "echo 'echo started\ncd missing' > .vfsrc";
+        assert_eq!(shell.run(buffer), Ok(None));
+        let overlay = &mut Shell::init_overlay(shell.root.clone());
+        assert_eq!(overlay.rc, Err(CommandError::Partial("started".to_string(), Box::new(CommandError::NotFound))));
+
+        // a transaction begun by the rc file stays open
+        let buffer = "echo 'begin\nmkdir draft' > .vfsrc";
+        assert_eq!(shell.run(buffer), Ok(None));
+        let overlay = &mut Shell::init_overlay(shell.root.clone());
+        assert!(overlay.in_transaction());
+        assert_eq!(overlay.run("rollback; ls draft"), Err(CommandError::NotFound));
+
+        let root = FileNode::create_directory("".to_string(), vec![]).to_pointer();
+        root.borrow_mut().connect(root.clone());
+        touch(&root, RC_FILE.to_string(), "alias ok=true".to_string());
+        let shell = &mut Shell::init_with_root(root);
+        assert_eq!(shell.run("ok; echo $?"), Ok(Some("0".to_string())));
+    }
+
+    #[test]
+    fn test_loop() {
+        let shell = &mut Shell::init();
+
+        let buffer = "for name in a 'b c' d; do touch \"$name\"; done; ls";
+        assert_eq!(shell.run(buffer), Ok(Some("a\tb c\td".to_string())));
+
+        let buffer = "for name in *; do echo \"<$name>\"; done";
+        assert_eq!(shell.run(buffer), Ok(Some("<a>\n<b c>\n<d>".to_string())));
+
+        let buffer = "for x in 1 2 3 4; do if read $x; then continue; fi; echo $x; if true; then break; fi; done";
+        assert_eq!(shell.run(buffer), Ok(Some("not found.\n1".to_string())));
+
+        let buffer = "for x in; do echo $x; done";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "mkdir flags; cd flags; touch a; touch b; cd /; while rm flags/*; do echo removed; done";
+        assert_eq!(shell.run(buffer), Ok(Some("removed\nnot found.".to_string())));
+
+        let buffer = "until true; do echo never; done";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "for x in a b; do echo $x; done | sort -r";
+        assert_eq!(shell.run(buffer), Ok(Some("b\na".to_string())));
+
+        let buffer = "break";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+    }
+
+    #[test]
+    fn test_function() {
+        let shell = &mut Shell::init();
+
+        let buffer = "greet() { echo \"hello $1 of $#\"; }";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "greet world extra";
+        assert_eq!(shell.run(buffer), Ok(Some("hello world of 2".to_string())));
+
+        let buffer = "function each {\n  for x in \"$@\"; do greet \"$x\"; done\n}\neach a 'b c'";
+        assert_eq!(shell.run(buffer), Ok(Some("hello a of 1\nhello b c of 1".to_string())));
+
+        let buffer = "first() { for x; do echo $x; return; done; }; first a b; echo $?";
+        assert_eq!(shell.run(buffer), Ok(Some("a\n0".to_string())));
+
+        let buffer = "fail() { echo before; return 3; echo after; }; fail";
+        let result = shell.run(buffer);
+        assert_eq!(result, Err(CommandError::Partial("before".to_string(), Box::new(CommandError::Failure(3)))));
+        assert_eq!(result.unwrap_err().to_string(), "before");
+
+        let buffer = "pwd; cd missing";
+        let result = shell.run(buffer);
+        assert_eq!(result, Err(CommandError::Partial("/".to_string(), Box::new(CommandError::NotFound))));
+        assert_eq!(result.unwrap_err().to_string(), "/\nnot found.");
+
+        let buffer = "fail || echo $?";
+        assert_eq!(shell.run(buffer), Ok(Some("before\n3".to_string())));
+
+        let buffer = "args() { echo $1; shift; echo $@; shift 5; }; args a b c";
+        assert_eq!(shell.run(buffer), Err(CommandError::Partial("a\nb c".to_string(), Box::new(CommandError::IllegalArgument))));
+
+        let buffer = "count=0; loop() { count=x$count; loop; }; loop";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "touch script; write script 'echo $2 $1'; sh script a b";
+        assert_eq!(shell.run(buffer), Ok(Some("b a".to_string())));
+
+        let buffer = "return";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "n=1 m=\"$n 2\"; echo $m; echo x=1";
+        assert_eq!(shell.run(buffer), Ok(Some("1 2\nx=1".to_string())));
+    }
+
+    #[test]
+    fn test_find() {
+        let shell = &mut Shell::init();
+
+        let buffer = "find";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "find a";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+
+        let buffer = "find b c";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+
+        let buffer = "mkdir a";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "find a";
+        assert_eq!(shell.run(buffer), Ok(Some("a".to_string())));
+
+        let buffer = "mkdir b";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "find b c";
+        assert_eq!(shell.run(buffer), Ok(Some("b".to_string())));
+    }
+
+    #[test]
+    fn test_mkdir() {
+        let shell = &mut Shell::init();
+
+        let buffer = "mkdir";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "mkdir a";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "mkdir b c";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "mkdir a";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("a\ta\tb".to_string())));
+    }
+
+    #[test]
+    fn test_touch() {
+        let shell = &mut Shell::init();
+
+        let buffer = "touch";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "touch a";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "touch b c";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "touch a";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("a\ta\tb".to_string())));
+    }
+
+    #[test]
+    fn test_read() {
+        let shell = &mut Shell::init();
+
+        let buffer = "read";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "read a";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+
+        let buffer = "read a b";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+
+        let buffer = "touch a";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "read a";
+        assert_eq!(shell.run(buffer), Ok(Some("".to_string())));
+
+        let buffer = "write a 123";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "read a";
+        assert_eq!(shell.run(buffer), Ok(Some("123".to_string())));
+
+        let buffer = "read a b";
+        assert_eq!(shell.run(buffer), Ok(Some("123".to_string())));
+
+        let buffer = "mkdir dir";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "read dir";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFile));
+    }
+
+    #[test]
+    fn test_write() {
+        let shell = &mut Shell::init();
+
+        let buffer = "write";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "write a";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "write a 123";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+
+        let buffer = "touch a";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "read a";
+        assert_eq!(shell.run(buffer), Ok(Some("".to_string())));
+
+        let buffer = "write a";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "write a 123";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "read a";
+        assert_eq!(shell.run(buffer), Ok(Some("123".to_string())));
+
+        let buffer = "write a 123 456 xyz";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "read a";
+        assert_eq!(shell.run(buffer), Ok(Some("123123 456 xyz".to_string())));
+
+        let buffer = "mkdir dir";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "write dir string";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFile));
+    }
+
+    #[test]
+    fn test_readonly() {
+        let shell = &mut Shell::init();
+
+        let buffer = "mkdir dir";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "touch a";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        shell.set_readonly(true);
+
+        let buffer = "mkdir b";
+        assert_eq!(shell.run(buffer), Err(CommandError::ReadOnly));
+
+        let buffer = "touch b";
+        assert_eq!(shell.run(buffer), Err(CommandError::ReadOnly));
+
+        let buffer = "write a 123";
+        assert_eq!(shell.run(buffer), Err(CommandError::ReadOnly));
+
+        let buffer = "cd dir";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "touch b";
+        assert_eq!(shell.run(buffer), Err(CommandError::ReadOnly));
+
+        let buffer = "cd ..";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("a\tdir".to_string())));
+
+        let buffer = "read a";
+        assert_eq!(shell.run(buffer), Ok(Some("".to_string())));
+
+        shell.set_readonly(false);
+
+        let buffer = "write a 123";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "read a";
+        assert_eq!(shell.run(buffer), Ok(Some("123".to_string())));
+
+        // the tree is not replaced by a snapshot
+        let buffer = "snapshot s1; echo 456 > a";
+        assert_eq!(shell.run(buffer), Ok(None));
+        shell.set_readonly(true);
+
+        let buffer = "restore s1";
+        assert_eq!(shell.run(buffer), Err(CommandError::ReadOnly));
+
+        let buffer = "read a";
+        assert_eq!(shell.run(buffer), Ok(Some("456\n".to_string())));
+    }
+
+    #[test]
+    fn test_mount() {
+        let fixture = &mut Shell::init();
+        assert_eq!(fixture.run("mkdir sub"), Ok(None));
+        assert_eq!(fixture.run("touch file"), Ok(None));
+        assert_eq!(fixture.run("write file fixture"), Ok(None));
+
+        let shell = &mut Shell::init();
+        assert_eq!(shell.mount("fixture", fixture.root.clone(), true), Ok(()));
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("fixture".to_string())));
+
+        let buffer = "cd fixture";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "pwd";
+        assert_eq!(shell.run(buffer), Ok(Some("/fixture".to_string())));
+
+        let buffer = "read file";
+        assert_eq!(shell.run(buffer), Ok(Some("fixture".to_string())));
+
+        let buffer = "write file 123";
+        assert_eq!(shell.run(buffer), Err(CommandError::ReadOnly));
+
+        let buffer = "cd sub";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "mkdir dir";
+        assert_eq!(shell.run(buffer), Err(CommandError::ReadOnly));
+
+        let buffer = "cd /";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "mkdir dir";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("dir\tfixture".to_string())));
+
+        // the mounted tree itself is left as it was
+        assert_eq!(fixture.run("cd sub; pwd"), Ok(Some("/sub".to_string())));
+        assert_eq!(fixture.run("cd ..; ls"), Ok(Some("file\tsub".to_string())));
+
+        // a commit can not be checked out over a read-only mount
+        let id = shell.commit_tree("c");
+        assert_eq!(shell.checkout_commit(&id), Err(CommandError::ReadOnly));
+        let buffer = "cd fixture; write file x";
+        assert_eq!(shell.run(buffer), Err(CommandError::ReadOnly));
+        let buffer = "read file";
+        assert_eq!(shell.run(buffer), Ok(Some("fixture".to_string())));
+    }
+
+    #[test]
+    fn test_rm() {
+        let shell = &mut Shell::init();
+
+        let buffer = "rm";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "rm a";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+
+        let buffer = "mkdir a";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "touch b";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "rm a";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("b".to_string())));
+
+        let buffer = "rm b";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("".to_string())));
+
+        let buffer = "mkdir a; cd a; touch b; cd ..";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "rm a";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "rm -r a";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("".to_string())));
+
+        // a read-only mount point can not be removed from a writable parent
+        let fixture = &mut Shell::init();
+        assert_eq!(shell.mount("fixture", fixture.root.clone(), true), Ok(()));
+
+        let buffer = "rm fixture";
+        assert_eq!(shell.run(buffer), Err(CommandError::ReadOnly));
+
+        // nothing is removed unless every path can be removed
+        let buffer = "touch a; touch b; rm a b missing";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+
+        let buffer = "rm a b fixture";
+        assert_eq!(shell.run(buffer), Err(CommandError::ReadOnly));
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("a\tb\tfixture".to_string())));
+
+        // the paths are restored together
+        let buffer = "rm a b; undo; ls";
+        assert_eq!(shell.run(buffer), Ok(Some("a\tb\tfixture".to_string())));
+    }
+
+    #[test]
+    fn test_overlay() {
+        let fixture = &mut Shell::init();
+        assert_eq!(fixture.run("mkdir sub"), Ok(None));
+        assert_eq!(fixture.run("touch file"), Ok(None));
+        assert_eq!(fixture.run("write file fixture"), Ok(None));
+        assert_eq!(fixture.run("cd sub"), Ok(None));
+        assert_eq!(fixture.run("touch nested"), Ok(None));
+
+        let shell = &mut Shell::init_overlay(fixture.root.clone());
+        let other = &mut Shell::init_overlay(fixture.root.clone());
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("file\tsub".to_string())));
+
+        let buffer = "read file";
+        assert_eq!(shell.run(buffer), Ok(Some("fixture".to_string())));
+
+        let buffer = "write file 123";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "read file";
+        assert_eq!(shell.run(buffer), Ok(Some("fixture123".to_string())));
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("file\tsub".to_string())));
+
+        let buffer = "cd sub";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "pwd";
+        assert_eq!(shell.run(buffer), Ok(Some("/sub".to_string())));
+
+        let buffer = "touch added";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "rm nested";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("added".to_string())));
+
+        let buffer = "read nested";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+
+        let buffer = "touch nested";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("added\tnested".to_string())));
+
+        let buffer = "cd ..";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "rm -r sub";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("file".to_string())));
+
+        // neither the lower tree nor other sessions see the modifications
+        let buffer = "ls";
+        assert_eq!(fixture.run("cd /"), Ok(None));
+        assert_eq!(fixture.run(buffer), Ok(Some("file\tsub".to_string())));
+        assert_eq!(fixture.run("read file"), Ok(Some("fixture".to_string())));
+        assert_eq!(other.run(buffer), Ok(Some("file\tsub".to_string())));
+        assert_eq!(other.run("read file"), Ok(Some("fixture".to_string())));
+        assert_eq!(other.run("cd sub"), Ok(None));
+        assert_eq!(other.run(buffer), Ok(Some("nested".to_string())));
+
+        // lookups leave the tree unchanged, a directory of the lower layer is
+        // only connected to the upper layer when it is modified
+        other.set_readonly(true);
+        assert_eq!(other.run("cd /; read sub/nested; cd sub; cd /"), Ok(Some("".to_string())));
+        other.complete("ls sub/", 7);
+        assert_eq!(other.root.borrow().1.len(), 1);
+        other.set_readonly(false);
+        assert_eq!(other.run("cd sub; echo x > /sub/added; ls"), Ok(Some("added\tnested".to_string())));
+        assert_eq!(other.root.borrow().1.len(), 2);
+        assert_eq!(other.run("undo; ls"), Ok(Some("nested".to_string())));
+        assert_eq!(other.root.borrow().1.len(), 1);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let shell = &mut Shell::init();
+
+        let buffer = "snapshot";
+        assert_eq!(shell.run(buffer), Ok(Some("".to_string())));
+
+        let buffer = "restore";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "restore a";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+
+        let buffer = "mkdir dir";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "touch file";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "write file 123";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "snapshot a";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "cd dir";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "touch nested";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "cd ..";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "write file 456";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "touch other";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "snapshot b";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "rm other";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "snapshot -l";
+        assert_eq!(shell.run(buffer), Ok(Some("a\tb".to_string())));
+
+        let buffer = "snapshot -d";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "snapshot -d a c";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+
+        let buffer = "snapshot -d a b";
+        assert_eq!(shell.run(buffer), Ok(Some("A /dir/nested\nM /file\nA /other".to_string())));
+
+        let buffer = "snapshot -d b";
+        assert_eq!(shell.run(buffer), Ok(Some("D /other".to_string())));
+
+        let buffer = "cd dir";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "restore a";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "pwd";
+        assert_eq!(shell.run(buffer), Ok(Some("/dir".to_string())));
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("".to_string())));
+
+        let buffer = "cd ..";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "read file";
+        assert_eq!(shell.run(buffer), Ok(Some("123".to_string())));
+
+        let buffer = "write file 789";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "restore b";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("dir\tfile\tother".to_string())));
+
+        let buffer = "read file";
+        assert_eq!(shell.run(buffer), Ok(Some("123456".to_string())));
+
+        let buffer = "restore a";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "read file";
+        assert_eq!(shell.run(buffer), Ok(Some("123".to_string())));
+
+        // snapshots of snapshots do not stack up layers below the root
+        let fixture = &mut Shell::init();
+        assert_eq!(fixture.run("mkdir sub"), Ok(None));
+        assert_eq!(shell.mount("ro", fixture.root.clone(), true), Ok(()));
+        for name in &["c", "d", "e"] {
+            assert_eq!(shell.run("write file 1; cd /ro/sub; cd /"), Ok(None));
+            assert_eq!(shell.run(&format!("snapshot {}", name)), Ok(None));
+        }
+        let layers = |shell: &Shell<_>| {
+            let mut count = 0;
+            let mut node = shell.root.clone();
+            loop {
+                let lower = if let FileType::Overlay{ name: _, lower } = &node.borrow().0 { lower.clone() } else { break };
+                node = lower;
+                count += 1;
+            }
+            count
+        };
+        assert_eq!(layers(shell), 1);
+
+        let buffer = "snapshot -d c e";
+        assert_eq!(shell.run(buffer), Ok(Some("M /file".to_string())));
+
+        let buffer = "cd ro/sub; mkdir dir";
+        assert_eq!(shell.run(buffer), Err(CommandError::ReadOnly));
+    }
+
+    #[test]
+    fn test_undo() {
+        let shell = &mut Shell::init();
+
+        let buffer = "undo";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+
+        let buffer = "redo";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+
+        let buffer = "mkdir dir";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "touch file";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "write file 123";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "rm dir";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "undo";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("dir\tfile".to_string())));
+
+        let buffer = "undo";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "read file";
+        assert_eq!(shell.run(buffer), Ok(Some("".to_string())));
+
+        let buffer = "undo";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("dir".to_string())));
+
+        let buffer = "redo";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "redo";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "read file";
+        assert_eq!(shell.run(buffer), Ok(Some("123".to_string())));
+
+        let buffer = "touch other";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "redo";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("dir\tfile\tother".to_string())));
+    }
+
+    #[test]
+    fn test_undo_overlay() {
+        let fixture = &mut Shell::init();
+        assert_eq!(fixture.run("touch file"), Ok(None));
+        assert_eq!(fixture.run("write file fixture"), Ok(None));
+
+        let shell = &mut Shell::init_overlay(fixture.root.clone());
+
+        let buffer = "write file 123";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "rm file";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("".to_string())));
+
+        let buffer = "undo";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "read file";
+        assert_eq!(shell.run(buffer), Ok(Some("fixture123".to_string())));
+
+        let buffer = "undo";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "read file";
+        assert_eq!(shell.run(buffer), Ok(Some("fixture".to_string())));
+        assert_eq!(fixture.run(buffer), Ok(Some("fixture".to_string())));
+    }
+
+    #[test]
+    fn test_transaction() {
+        let shell = &mut Shell::init();
+
+        let buffer = "commit";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "rollback";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "touch file";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "begin";
+        assert_eq!(shell.run(buffer), Ok(None));
+        assert!(shell.in_transaction());
+
+        let buffer = "mkdir dir";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "write file 123";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "rollback";
+        assert_eq!(shell.run(buffer), Ok(None));
+        assert!(!shell.in_transaction());
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("file".to_string())));
+
+        let buffer = "read file";
+        assert_eq!(shell.run(buffer), Ok(Some("".to_string())));
+
+        let buffer = "begin";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "mkdir dir";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "write file 123";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "commit";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("dir\tfile".to_string())));
+
+        // a committed transaction is undone as a whole
+        let buffer = "undo";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("file".to_string())));
+
+        let buffer = "read file";
+        assert_eq!(shell.run(buffer), Ok(Some("".to_string())));
+
+        let result = shell.transaction(|shell| {
+            shell.run("mkdir a")?;
+            shell.run("write a 123")?;
+            shell.run("mkdir b")
+        });
+        assert_eq!(result, Err(CommandError::NotFile));
+        assert!(!shell.in_transaction());
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("file".to_string())));
+
+        let result = shell.transaction(|shell| {
+            shell.run("mkdir a")?;
+            shell.run("write file 123")
+        });
+        assert_eq!(result, Ok(None));
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("a\tfile".to_string())));
+
+        // the tree can not be replaced while a transaction is open
+        let id = shell.commit_tree("one");
+        shell.snapshot("one").unwrap();
+
+        let buffer = "begin; touch x";
+        assert_eq!(shell.run(buffer), Ok(None));
+        assert_eq!(shell.checkout_commit(&id), Err(CommandError::IllegalArgument));
+        assert_eq!(shell.restore("one"), Err(CommandError::IllegalArgument));
+        assert_eq!(shell.snapshot("two"), Err(CommandError::IllegalArgument));
+
+        let buffer = "rollback";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("a\tfile".to_string())));
+
+        assert_eq!(shell.checkout_commit(&id), Ok(()));
+    }
+
+    #[test]
+    fn test_log() {
+        let shell = &mut Shell::init();
+
+        let buffer = "log";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "log a";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
 
-#[cfg(test)]
-mod test {
-    use crate::virtual_filesystem::shell::{CommandError, Shell};
+        let buffer = "touch a";
+        assert_eq!(shell.run(buffer), Ok(None));
 
-    #[test]
-    fn test_enter() {
-        let shell = &mut Shell::init();
+        let buffer = "log a";
+        assert_eq!(shell.run(buffer), Ok(Some("0\t".to_string())));
 
-        let buffer = "";
+        let buffer = "write a 123";
         assert_eq!(shell.run(buffer), Ok(None));
-    }
 
-    #[test]
-    fn test_exit() {
-        let shell = &mut Shell::init();
+        let buffer = "write a 456";
+        assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = "exit";
-        assert_eq!(shell.run(buffer), Err(CommandError::CommandNotFound(buffer.to_string())));
-    }
+        let buffer = "log a";
+        assert_eq!(shell.run(buffer), Ok(Some("2\t123456\n1\t123\n0\t".to_string())));
 
-    #[test]
-    fn test_help() {
-        let shell = &mut Shell::init();
+        let buffer = "mkdir dir";
+        assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = ":?";
-        assert_eq!(shell.run(buffer), Err(CommandError::CommandNotFound(buffer.to_string())));
+        let buffer = "log dir";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFile));
     }
 
     #[test]
-    fn test_ls() {
+    fn test_diff() {
         let shell = &mut Shell::init();
 
-        let buffer = "ls";
-        assert_eq!(shell.run(buffer), Ok(Some("".to_string())));
+        let buffer = "diff";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
 
-        let buffer = "ls a";
-        assert_eq!(shell.run(buffer), Ok(Some("".to_string())));
+        let buffer = "touch a";
+        assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = "ls b c";
-        assert_eq!(shell.run(buffer), Ok(Some("".to_string())));
+        let buffer = "diff a";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
 
-        let buffer = "mkdir a";
+        let buffer = "write a 123";
         assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = "ls";
-        assert_eq!(shell.run(buffer), Ok(Some("a".to_string())));
+        let buffer = "diff a 0";
+        assert_eq!(shell.run(buffer), Ok(Some("+123".to_string())));
 
-        let buffer = "ls a";
-        assert_eq!(shell.run(buffer), Ok(Some("a".to_string())));
+        let buffer = "diff a 1 0";
+        assert_eq!(shell.run(buffer), Ok(Some("-123".to_string())));
 
-        let buffer = "ls b c";
-        assert_eq!(shell.run(buffer), Ok(Some("a".to_string())));
+        let buffer = "diff a 5";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+
+        let buffer = "diff a x";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
     }
 
     #[test]
-    fn test_pwd() {
+    fn test_checkout() {
         let shell = &mut Shell::init();
 
-        let buffer = "pwd";
-        assert_eq!(shell.run(buffer), Ok(Some("/".to_string())));
+        let buffer = "checkout";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
 
-        let buffer = "pwd a";
-        assert_eq!(shell.run(buffer), Ok(Some("/".to_string())));
+        let buffer = "checkout a 0";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
 
-        let buffer = "mkdir a";
+        let buffer = "touch a";
         assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = "cd a";
+        let buffer = "write a 123";
         assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = "pwd";
-        assert_eq!(shell.run(buffer), Ok(Some("/a".to_string())));
+        let buffer = "write a 456";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "checkout a 1";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "read a";
+        assert_eq!(shell.run(buffer), Ok(Some("123".to_string())));
+
+        let buffer = "log a";
+        assert_eq!(shell.run(buffer), Ok(Some("3\t123\n2\t123456\n1\t123\n0\t".to_string())));
+
+        let buffer = "undo";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "read a";
+        assert_eq!(shell.run(buffer), Ok(Some("123456".to_string())));
+
+        // the versions follow undo and redo
+        let buffer = "log a";
+        assert_eq!(shell.run(buffer), Ok(Some("2\t123456\n1\t123\n0\t".to_string())));
+
+        let buffer = "redo; log a; undo";
+        assert_eq!(shell.run(buffer), Ok(Some("3\t123\n2\t123456\n1\t123\n0\t".to_string())));
+
+        let buffer = "checkout a 9";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+
+        shell.set_history_limit(1);
+
+        let buffer = "log a";
+        assert_eq!(shell.run(buffer), Ok(Some("2\t123456\n1\t123".to_string())));
     }
 
     #[test]
-    fn test_cd() {
+    fn test_commit() {
         let shell = &mut Shell::init();
 
-        let buffer = "cd";
+        let buffer = "log -c";
+        assert_eq!(shell.run(buffer), Ok(Some("".to_string())));
+
+        let buffer = "commit -m";
         assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
 
-        let buffer = "cd a";
-        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+        let buffer = "mkdir dir";
+        assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = "cd b c";
-        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+        let buffer = "touch file";
+        assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = "pwd";
-        assert_eq!(shell.run(buffer), Ok(Some("/".to_string())));
+        let buffer = "status";
+        assert_eq!(shell.run(buffer), Ok(Some("A /dir\nA /file".to_string())));
 
-        let buffer = "cd .";
-        assert_eq!(shell.run(buffer), Ok(None));
+        let first = shell.run("commit -m first commit").unwrap().unwrap();
+        assert_eq!(first.len(), 7);
 
-        let buffer = "pwd";
-        assert_eq!(shell.run(buffer), Ok(Some("/".to_string())));
+        let buffer = "status";
+        assert_eq!(shell.run(buffer), Ok(Some("".to_string())));
 
-        let buffer = "cd ..";
+        let buffer = "write file 123";
         assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = "pwd";
-        assert_eq!(shell.run(buffer), Ok(Some("/".to_string())));
-
-        let buffer = "mkdir a";
+        let buffer = "rm dir";
         assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = "cd a";
+        let buffer = "status";
+        assert_eq!(shell.run(buffer), Ok(Some("D /dir\nM /file".to_string())));
+
+        let second = shell.run("commit -m second").unwrap().unwrap();
+
+        let buffer = "log -c";
+        assert_eq!(shell.run(buffer), Ok(Some(format!("{}\tsecond\n{}\tfirst commit", second, first))));
+
+        let buffer = "checkout -c unknown";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+
+        let buffer = format!("checkout -c {}", first);
+        assert_eq!(shell.run(&buffer), Ok(None));
+
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("dir\tfile".to_string())));
+
+        let buffer = "read file";
+        assert_eq!(shell.run(buffer), Ok(Some("".to_string())));
+
+        let buffer = "status";
+        assert_eq!(shell.run(buffer), Ok(Some("".to_string())));
+
+        let third = shell.run("commit -m third").unwrap().unwrap();
+
+        let buffer = "log -c";
+        assert_eq!(shell.run(buffer), Ok(Some(format!("{}\tthird\n{}\tfirst commit", third, first))));
+
+        let buffer = format!("checkout -c {}", second);
+        assert_eq!(shell.run(&buffer), Ok(None));
+
+        let buffer = "read file";
+        assert_eq!(shell.run(buffer), Ok(Some("123".to_string())));
+
+        // a file is checked out from the last commit
+        let buffer = "write file 456; checkout file; read file";
+        assert_eq!(shell.run(buffer), Ok(Some("123".to_string())));
+
+        let buffer = "touch new; checkout new";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+
+        let buffer = format!("checkout -c {} file", second);
+        assert_eq!(shell.run(&buffer), Err(CommandError::IllegalArgument));
+    }
+
+    #[test]
+    fn test_quoting() {
+        let shell = &mut Shell::init();
+
+        let buffer = "touch 'my file'";
         assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = "cd .";
+        let buffer = "mkdir my\\ dir";
         assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = "pwd";
-        assert_eq!(shell.run(buffer), Ok(Some("/a".to_string())));
+        let buffer = "ls";
+        assert_eq!(shell.run(buffer), Ok(Some("my dir\tmy file".to_string())));
 
-        let buffer = "mkdir b";
+        let buffer = "write  \"my file\"   'my file  has'  spaces";
         assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = "cd b c";
-        assert_eq!(shell.run(buffer), Ok(None));
+        let buffer = "read \"my file\"";
+        assert_eq!(shell.run(buffer), Ok(Some("my file  has spaces".to_string())));
 
-        let buffer = "cd .";
+        let buffer = "cd   'my dir'";
         assert_eq!(shell.run(buffer), Ok(None));
 
         let buffer = "pwd";
-        assert_eq!(shell.run(buffer), Ok(Some("/a/b".to_string())));
+        assert_eq!(shell.run(buffer), Ok(Some("/my dir".to_string())));
 
-        let buffer = "cd ..";
+        let buffer = "touch a";
         assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = "pwd";
-        assert_eq!(shell.run(buffer), Ok(Some("/a".to_string())));
+        let buffer = "write a a";
+        assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = "cd /";
+        let buffer = "write a ''";
         assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = "pwd";
-        assert_eq!(shell.run(buffer), Ok(Some("/".to_string())));
+        let buffer = "read a";
+        assert_eq!(shell.run(buffer), Ok(Some("a".to_string())));
+
+        let buffer = "read 'a";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
     }
 
     #[test]
-    fn test_find() {
+    fn test_redirect() {
         let shell = &mut Shell::init();
 
-        let buffer = "find";
+        let buffer = "ls >";
         assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
 
-        let buffer = "find a";
+        let buffer = "wc < a";
         assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
 
-        let buffer = "find b c";
-        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+        let buffer = "mkdir dir";
+        assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = "mkdir a";
+        let buffer = "ls > listing";
         assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = "find a";
-        assert_eq!(shell.run(buffer), Ok(Some("a".to_string())));
+        let buffer = "read listing";
+        assert_eq!(shell.run(buffer), Ok(Some("dir\n".to_string())));
 
-        let buffer = "mkdir b";
+        let buffer = "ls>listing";
         assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = "find b c";
-        assert_eq!(shell.run(buffer), Ok(Some("b".to_string())));
-    }
+        let buffer = "read listing";
+        assert_eq!(shell.run(buffer), Ok(Some("dir\nlisting\n".to_string())));
 
-    #[test]
-    fn test_mkdir() {
-        let shell = &mut Shell::init();
+        let buffer = "pwd >> dir/log";
+        assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = "mkdir";
+        let buffer = "cd dir";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "pwd >> log";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "read log";
+        assert_eq!(shell.run(buffer), Ok(Some("/\n/dir\n".to_string())));
+
+        let buffer = "wc < log";
+        assert_eq!(shell.run(buffer), Ok(Some("2 2 7".to_string())));
+
+        let buffer = "wc log";
+        assert_eq!(shell.run(buffer), Ok(Some("2 2 7".to_string())));
+
+        let buffer = "wc";
         assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
 
-        let buffer = "mkdir a";
+        let buffer = "touch copy";
         assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = "mkdir b c";
+        let buffer = "write copy < /listing";
         assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = "mkdir a";
+        let buffer = "read copy > '>'";
         assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = "ls";
-        assert_eq!(shell.run(buffer), Ok(Some("a\tb\ta".to_string())));
+        let buffer = "read '>'";
+        assert_eq!(shell.run(buffer), Ok(Some("dir\nlisting\n\n".to_string())));
+
+        let buffer = "wc > dir";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "pwd > missing/file";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+
+        let buffer = "undo";
+        assert_eq!(shell.run(buffer), Ok(None));
+
+        let buffer = "read '>'";
+        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+
+        let buffer = "redo; read '>'";
+        assert_eq!(shell.run(buffer), Ok(Some("dir\nlisting\n\n".to_string())));
+
+        // an existing file keeps its previous contents
+        let buffer = "pwd >> log; undo; read log";
+        assert_eq!(shell.run(buffer), Ok(Some("/\n/dir\n".to_string())));
     }
 
     #[test]
-    fn test_touch() {
+    fn test_pipe() {
         let shell = &mut Shell::init();
 
-        let buffer = "touch";
+        let buffer = "ls |";
         assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
 
-        let buffer = "touch a";
+        let buffer = "| wc";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "mkdir banana";
         assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = "touch b c";
+        let buffer = "mkdir apple";
         assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = "touch a";
+        let buffer = "touch cherry";
         assert_eq!(shell.run(buffer), Ok(None));
 
         let buffer = "ls";
-        assert_eq!(shell.run(buffer), Ok(Some("a\tb\ta".to_string())));
-    }
+        assert_eq!(shell.run(buffer), Ok(Some("apple\tbanana\tcherry".to_string())));
 
-    #[test]
-    fn test_read() {
-        let shell = &mut Shell::init();
+        let buffer = "ls | wc";
+        assert_eq!(shell.run(buffer), Ok(Some("3 3 19".to_string())));
 
-        let buffer = "read";
-        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+        let buffer = "ls | sort";
+        assert_eq!(shell.run(buffer), Ok(Some("apple\nbanana\ncherry".to_string())));
 
-        let buffer = "read a";
-        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+        let buffer = "ls | sort -r | head -n 2";
+        assert_eq!(shell.run(buffer), Ok(Some("cherry\nbanana".to_string())));
 
-        let buffer = "read a b";
-        assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+        let buffer = "ls|grep an";
+        assert_eq!(shell.run(buffer), Ok(Some("banana".to_string())));
 
-        let buffer = "touch a";
+        let buffer = "ls | grep -v an | sort | wc";
+        assert_eq!(shell.run(buffer), Ok(Some("2 2 12".to_string())));
+
+        let buffer = "ls | sort > sorted";
         assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = "read a";
-        assert_eq!(shell.run(buffer), Ok(Some("".to_string())));
+        let buffer = "read sorted | head -n 1";
+        assert_eq!(shell.run(buffer), Ok(Some("apple".to_string())));
 
-        let buffer = "write a 123";
+        let buffer = "head -n 1 sorted";
+        assert_eq!(shell.run(buffer), Ok(Some("apple".to_string())));
+
+        let buffer = "grep e sorted | sort -r";
+        assert_eq!(shell.run(buffer), Ok(Some("cherry\napple".to_string())));
+
+        let buffer = "ls | write cherry";
         assert_eq!(shell.run(buffer), Ok(None));
 
-        let buffer = "read a";
-        assert_eq!(shell.run(buffer), Ok(Some("123".to_string())));
+        let buffer = "wc < cherry | grep 4";
+        assert_eq!(shell.run(buffer), Ok(Some("4 4 26".to_string())));
 
-        let buffer = "read a b";
-        assert_eq!(shell.run(buffer), Ok(Some("123".to_string())));
+        let buffer = "ls > listing | wc";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
 
-        let buffer = "mkdir dir";
-        assert_eq!(shell.run(buffer), Ok(None));
+        let buffer = "grep";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
 
-        let buffer = "read dir";
-        assert_eq!(shell.run(buffer), Err(CommandError::NotFile));
+        let buffer = "head -n x sorted";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "sort";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
     }
 
     #[test]
-    fn test_write() {
+    fn test_sequence() {
         let shell = &mut Shell::init();
 
-        let buffer = "write";
-        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
-
-        let buffer = "write a";
-        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+        let buffer = "mkdir out && cd out && pwd";
+        assert_eq!(shell.run(buffer), Ok(Some("/out".to_string())));
+        assert_eq!(shell.exit_status, 0);
 
-        let buffer = "write a 123";
+        let buffer = "cd missing && pwd";
         assert_eq!(shell.run(buffer), Err(CommandError::NotFound));
+        assert_eq!(shell.exit_status, 1);
 
-        let buffer = "touch a";
-        assert_eq!(shell.run(buffer), Ok(None));
+        let buffer = "echo $?";
+        assert_eq!(shell.run(buffer), Ok(Some("1".to_string())));
 
-        let buffer = "read a";
-        assert_eq!(shell.run(buffer), Ok(Some("".to_string())));
+        let buffer = "echo $?";
+        assert_eq!(shell.run(buffer), Ok(Some("0".to_string())));
 
-        let buffer = "write a";
-        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+        let buffer = "cd missing || cd /; pwd";
+        assert_eq!(shell.run(buffer), Ok(Some("not found.\n/".to_string())));
 
-        let buffer = "write a 123";
-        assert_eq!(shell.run(buffer), Ok(None));
+        let buffer = "pwd || cd out";
+        assert_eq!(shell.run(buffer), Ok(Some("/".to_string())));
 
-        let buffer = "read a";
-        assert_eq!(shell.run(buffer), Ok(Some("123".to_string())));
+        let buffer = "unknown; echo \"status $?\"";
+        assert_eq!(shell.run(buffer), Ok(Some("unknown command not found.\nstatus 127".to_string())));
 
-        let buffer = "write a 123 456 xyz";
-        assert_eq!(shell.run(buffer), Ok(None));
+        let buffer = "echo '$?'; echo a;";
+        assert_eq!(shell.run(buffer), Ok(Some("$?\na".to_string())));
 
-        let buffer = "read a";
-        assert_eq!(shell.run(buffer), Ok(Some("123123 456 xyz".to_string())));
+        let buffer = "ls | wc && echo $?";
+        assert_eq!(shell.run(buffer), Ok(Some("1 1 3\n0".to_string())));
 
-        let buffer = "mkdir dir";
-        assert_eq!(shell.run(buffer), Ok(None));
+        let buffer = "cd missing; pwd > cwd && read cwd";
+        assert_eq!(shell.run(buffer), Ok(Some("not found.\n/\n".to_string())));
 
-        let buffer = "write dir string";
-        assert_eq!(shell.run(buffer), Err(CommandError::NotFile));
+        let buffer = "; ls";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "ls &&";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
+
+        let buffer = "ls ;; ls";
+        assert_eq!(shell.run(buffer), Err(CommandError::IllegalArgument));
     }
 }
-
@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use crate::virtual_filesystem_core::graph::Graph;
+use crate::virtual_filesystem_core::hash::{sha256, to_hex};
+use crate::virtual_filesystem_core::filesystem::{FileNode, FileNodePointer, FileType, FileObject, Name, Data};
+use crate::virtual_filesystem::command::{children, mkdir, touch};
+use crate::virtual_filesystem::shell::CommandError;
+
+
+pub type ObjectId = String;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Object {
+    Blob(Data),
+    Tree(Vec<(Name, ObjectId)>),
+    Commit {
+        tree: ObjectId,
+        parent: Option<ObjectId>,
+        message: String,
+    },
+}
+
+
+#[derive(Debug, Clone, Default)]
+pub struct ObjectStore {
+    objects: HashMap<ObjectId, Object>,
+    pub head: Option<ObjectId>,
+}
+
+
+impl Object {
+    fn encode(&self) -> Vec<u8> {
+        let mut result = vec![];
+        match self {
+            Object::Blob(data) => {
+                result.extend(format!("blob {}\0", data.len()).bytes());
+                result.extend(data.bytes());
+            },
+            Object::Tree(entries) => {
+                result.extend(b"tree\0");
+                for (name, id) in entries {
+                    result.extend(format!("{} {}:{}\0", id, name.len(), name).bytes());
+                }
+            },
+            Object::Commit{ tree, parent, message } => {
+                result.extend(format!("commit\0tree {}\0", tree).bytes());
+                if let Some(parent) = parent {
+                    result.extend(format!("parent {}\0", parent).bytes());
+                }
+                result.extend(format!("\0{}", message).bytes());
+            },
+        }
+        result
+    }
+
+    pub fn id(&self) -> ObjectId {
+        to_hex(&sha256(&self.encode()))
+    }
+}
+
+
+impl ObjectStore {
+    pub fn new() -> ObjectStore {
+        ObjectStore::default()
+    }
+
+    pub fn put(&mut self, object: Object) -> ObjectId {
+        let id = object.id();
+        self.objects.entry(id.to_string()).or_insert(object);
+        id
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Object> {
+        self.objects.get(id)
+    }
+
+    pub fn lookup(&self, prefix: &str) -> Result<ObjectId, CommandError> {
+        if prefix.is_empty() { return Err(CommandError::IllegalArgument); }
+        let found: Vec<&ObjectId> = self.objects.iter()
+            .filter(|(id, object)| id.starts_with(prefix) && matches!(object, Object::Commit{ .. }))
+            .map(|(id, _)| id)
+            .collect();
+        match found.as_slice() {
+            [id] => Ok(id.to_string()),
+            [] => Err(CommandError::NotFound),
+            _ => Err(CommandError::IllegalArgument),
+        }
+    }
+
+    pub fn write_tree(&mut self, directory: &FileNodePointer) -> ObjectId {
+        let mut entries = vec![];
+        for node in children(directory) {
+            let id = match &node.borrow().0 {
+                FileType::File{ name: _, data } => self.put(Object::Blob(data.to_string())),
+                x if x.is_directory() => self.write_tree(&node),
+                _ => continue,
+            };
+            entries.push((node.borrow().0.name().to_string(), id));
+        }
+        self.put(Object::Tree(entries))
+    }
+
+    pub fn read_tree(&self, id: &str) -> Result<FileNodePointer, CommandError> {
+        fn walk(store: &ObjectStore, id: &str, directory: &FileNodePointer) -> Result<(), CommandError> {
+            let entries = match store.get(id) {
+                Some(Object::Tree(entries)) => entries,
+                _ => return Err(CommandError::NotFound),
+            };
+            for (name, id) in entries {
+                match store.get(id) {
+                    Some(Object::Blob(data)) => { touch(directory, name.to_string(), data.to_string()); },
+                    Some(Object::Tree(_)) => {
+                        mkdir(directory, name.to_string());
+                        let child = directory.borrow().1.last().cloned().ok_or(CommandError::UnknownError)?;
+                        walk(store, id, &child)?;
+                    },
+                    _ => return Err(CommandError::NotFound),
+                }
+            }
+            Ok(())
+        }
+
+        let root = FileNode::create_directory("".to_string(), vec![]).to_pointer();
+        root.borrow_mut().connect(root.clone());
+        walk(self, id, &root)?;
+        Ok(root)
+    }
+
+    pub fn commit(&mut self, root: &FileNodePointer, message: &str) -> ObjectId {
+        let tree = self.write_tree(root);
+        let id = self.put(Object::Commit {
+            tree,
+            parent: self.head.clone(),
+            message: message.to_string(),
+        });
+        self.head = Some(id.to_string());
+        id
+    }
+
+    pub fn log(&self) -> Vec<(ObjectId, String)> {
+        let mut result = vec![];
+        let mut position = self.head.clone();
+        while let Some(id) = position {
+            position = match self.get(&id) {
+                Some(Object::Commit{ tree: _, parent, message }) => {
+                    result.push((id.to_string(), message.to_string()));
+                    parent.clone()
+                },
+                _ => None,
+            };
+        }
+        result
+    }
+
+    pub fn tree_of(&self, id: &str) -> Result<ObjectId, CommandError> {
+        match self.get(id) {
+            Some(Object::Commit{ tree, parent: _, message: _ }) => Ok(tree.to_string()),
+            _ => Err(CommandError::NotFound),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::virtual_filesystem::shell::Shell;
+    use crate::virtual_filesystem::persistence::serialize;
+    use crate::virtual_filesystem::store::{Object, ObjectStore};
+
+    #[test]
+    fn test_object() {
+        let blob = Object::Blob("data".to_string());
+        assert_eq!(blob.id(), Object::Blob("data".to_string()).id());
+        assert_ne!(blob.id(), Object::Blob("other".to_string()).id());
+        assert_ne!(
+            Object::Tree(vec![("a".to_string(), blob.id())]).id(),
+            Object::Tree(vec![("b".to_string(), blob.id())]).id()
+        );
+    }
+
+    #[test]
+    fn test_store() {
+        let shell = &mut Shell::init();
+        assert_eq!(shell.run("mkdir dir"), Ok(None));
+        assert_eq!(shell.run("touch file"), Ok(None));
+        assert_eq!(shell.run("write file 123"), Ok(None));
+        assert_eq!(shell.run("cd dir"), Ok(None));
+        assert_eq!(shell.run("touch same"), Ok(None));
+        assert_eq!(shell.run("write same 123"), Ok(None));
+
+        let store = &mut ObjectStore::new();
+        let first = store.commit(&shell.root, "first");
+        let tree = store.tree_of(&first).unwrap();
+        assert_eq!(serialize(&store.read_tree(&tree).unwrap()), serialize(&shell.root));
+
+        // identical contents are stored once
+        assert_eq!(store.objects.len(), 4);
+
+        let second = store.commit(&shell.root, "second");
+        assert_ne!(first, second);
+        assert_eq!(store.objects.len(), 5);
+        assert_eq!(store.log(), vec![(second.to_string(), "second".to_string()), (first.to_string(), "first".to_string())]);
+
+        assert_eq!(store.lookup(&first[..7]), Ok(first.to_string()));
+        assert!(store.lookup(&tree).is_err());
+        assert!(store.lookup("").is_err());
+    }
+}
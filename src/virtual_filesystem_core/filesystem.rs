@@ -17,7 +17,14 @@ pub enum FileType {
     File {
         name: Name,
         data: Data,
-    }
+    },
+    Overlay {
+        name: Name,
+        lower: FileNodePointer,
+    },
+    Whiteout {
+        name: Name,
+    },
 }
 
 
@@ -31,16 +38,25 @@ impl FileObject for FileType {
         match self {
             FileType::Directory{ name } => { name },
             FileType::File{ name, data: _ } => { name },
+            FileType::Overlay{ name, lower: _ } => { name },
+            FileType::Whiteout{ name } => { name },
         }
     }
 }
 
 
+impl FileType {
+    pub fn is_directory(&self) -> bool {
+        matches!(self, FileType::Directory{ .. } | FileType::Overlay{ .. })
+    }
+}
+
+
 impl FileNode {
     pub fn create_directory(name: Name, edge: Edge<FileType>) -> FileNode {
         Node(
             FileType::Directory {
-                name: name,
+                name,
             },
             edge,
         )
@@ -49,8 +65,27 @@ impl FileNode {
     pub fn create_file(name: Name, data: Data, edge: Edge<FileType>) -> FileNode {
         Node(
             FileType::File {
-                name: name,
-                data: data,
+                name,
+                data,
+            },
+            edge,
+        )
+    }
+
+    pub fn create_overlay(name: Name, lower: FileNodePointer, edge: Edge<FileType>) -> FileNode {
+        Node(
+            FileType::Overlay {
+                name,
+                lower,
+            },
+            edge,
+        )
+    }
+
+    pub fn create_whiteout(name: Name, edge: Edge<FileType>) -> FileNode {
+        Node(
+            FileType::Whiteout {
+                name,
             },
             edge,
         )
@@ -72,7 +107,7 @@ impl Graph for FileNode {
 
 #[cfg(test)]
 mod tests_file_object {
-    use crate::virtual_filesystem_core::filesystem::{FileType, FileObject};
+    use crate::virtual_filesystem_core::filesystem::{FileNode, FileType, FileObject};
 
     #[test]
     fn test_name() {
@@ -81,6 +116,29 @@ mod tests_file_object {
 
         let file = FileType::File{ name: "file".to_string(), data: "data".to_string() };
         assert_eq!(file.name(), "file");
+
+        let lower = FileNode::create_directory("lower".to_string(), vec![]).to_pointer();
+        let overlay = FileType::Overlay{ name: "overlay".to_string(), lower };
+        assert_eq!(overlay.name(), "overlay");
+
+        let whiteout = FileType::Whiteout{ name: "whiteout".to_string() };
+        assert_eq!(whiteout.name(), "whiteout");
+    }
+
+    #[test]
+    fn test_is_directory() {
+        let directory = FileType::Directory{ name: "directory".to_string() };
+        assert!(directory.is_directory());
+
+        let file = FileType::File{ name: "file".to_string(), data: "data".to_string() };
+        assert!(!file.is_directory());
+
+        let lower = FileNode::create_directory("lower".to_string(), vec![]).to_pointer();
+        let overlay = FileType::Overlay{ name: "overlay".to_string(), lower };
+        assert!(overlay.is_directory());
+
+        let whiteout = FileType::Whiteout{ name: "whiteout".to_string() };
+        assert!(!whiteout.is_directory());
     }
 }
 
@@ -137,9 +195,27 @@ mod tests_file_node {
 
 #[cfg(test)]
 mod tests_graph {
-    use crate::virtual_filesystem_core::graph::{Node, Edge};
+    use crate::virtual_filesystem_core::graph::{Node, NodeMap, Edge};
     use crate::virtual_filesystem_core::filesystem::{FileNode, FileType};
 
+    #[test]
+    fn test_node_map() {
+        let map = &mut NodeMap::default();
+        let file = FileNode::create_file("file".to_string(), "".to_string(), vec![]).to_pointer();
+        let other = FileNode::create_file("file".to_string(), "".to_string(), vec![]).to_pointer();
+
+        map.insert(&file, 1);
+        assert_eq!(map.get(&file), Some(&1));
+        assert_eq!(map.get(&other), None);
+
+        *map.get_mut(&file).unwrap() = 2;
+        assert_eq!(map.get(&file), Some(&2));
+
+        drop(file);
+        map.prune();
+        assert!(map.is_empty());
+    }
+
     #[test]
     fn test_edge() {
         let node1 = Node(
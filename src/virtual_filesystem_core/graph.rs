@@ -1,4 +1,5 @@
-use std::rc::Rc;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
 use std::cell::RefCell;
 
 
@@ -6,6 +7,7 @@ use std::cell::RefCell;
 pub struct Node<T>(pub T, pub Edge<T>);
 pub type NodePointer<T> = Rc<RefCell<Node<T>>>;
 pub type Edge<T> = Vec<NodePointer<T>>;
+type WeakPointer<T> = Weak<RefCell<Node<T>>>;
 
 
 pub trait Graph {
@@ -13,3 +15,60 @@ pub trait Graph {
     fn connect(&mut self, node: NodePointer<Self::NodeType>);
 }
 
+
+// values attached to nodes without keeping the nodes alive. a node is found
+// by its address, which can only be reused once the node is dropped, and
+// `prune` removes the entries of dropped nodes.
+#[derive(Debug)]
+pub struct NodeMap<T, V> {
+    entries: HashMap<usize, (WeakPointer<T>, V)>,
+}
+
+
+impl<T, V> Default for NodeMap<T, V> {
+    fn default() -> NodeMap<T, V> {
+        NodeMap { entries: HashMap::new() }
+    }
+}
+
+
+impl<T, V: Clone> Clone for NodeMap<T, V> {
+    fn clone(&self) -> NodeMap<T, V> {
+        NodeMap { entries: self.entries.clone() }
+    }
+}
+
+
+impl<T, V> NodeMap<T, V> {
+    fn key(node: &NodePointer<T>) -> usize {
+        Rc::as_ptr(node) as usize
+    }
+
+    pub fn get(&self, node: &NodePointer<T>) -> Option<&V> {
+        self.entries.get(&Self::key(node))
+            .filter(|x| x.0.strong_count() > 0)
+            .map(|x| &x.1)
+    }
+
+    pub fn get_mut(&mut self, node: &NodePointer<T>) -> Option<&mut V> {
+        self.entries.get_mut(&Self::key(node))
+            .filter(|x| x.0.strong_count() > 0)
+            .map(|x| &mut x.1)
+    }
+
+    pub fn insert(&mut self, node: &NodePointer<T>, value: V) {
+        self.entries.insert(Self::key(node), (Rc::downgrade(node), value));
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V> {
+        self.entries.values_mut().map(|x| &mut x.1)
+    }
+
+    pub fn prune(&mut self) {
+        self.entries.retain(|_, x| x.0.strong_count() > 0);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
@@ -1,3 +1,4 @@
 pub mod graph;
 pub mod filesystem;
+pub mod hash;
 pub mod logger;
\ No newline at end of file